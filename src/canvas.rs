@@ -1,22 +1,89 @@
-use std::{collections::BTreeMap, rc::Rc, sync::atomic::AtomicUsize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::Path,
+    rc::Rc,
+    sync::atomic::AtomicUsize,
+    time::Duration,
+};
 
 use druid::{
-    im, Affine, BoxConstraints, Color, Data, MouseButton, Point, Rect, RenderContext, Selector,
-    Size, Vec2, Widget, WidgetId, WidgetPod,
+    commands, im,
+    kurbo::{BezPath, Circle, Shape},
+    piet::{StrokeStyle, Text, TextLayout, TextLayoutBuilder},
+    theme, Affine, Application, BoxConstraints, Color, Cursor, Data, FileDialogOptions, FontFamily,
+    MouseButton, MouseEvent, Point, Rect, RenderContext, Selector, Size, TimerToken, Vec2, Widget,
+    WidgetId, WidgetPod,
 };
 
 use crate::{
-    component::{Component, ComponentInstance, ComponentState, ComponentType, Orientation},
-    wire::{Wire, WireSegment, WireState},
+    component::{
+        Component, ComponentInstance, ComponentState, ComponentType, Function, Orientation,
+        PinType, MAX_CLOCK_PERIOD, MIN_CLOCK_PERIOD,
+    },
+    history::{Circuit, History},
+    keymap::{Action, Keymap},
+    netlist::{Netlist, CIRCUIT_FILE_TYPE},
+    sim::{self, PinRef, Probe, SimError, TruthTable},
+    theme::{
+        CONNECT_TARGET_COLOR, REFUSED_TARGET_COLOR, SECONDARY_SELECTION_COLOR, SELECTION_COLOR,
+        SHOW_PINS,
+    },
+    wire::{Wire, WireEnd, WireSegment, WireState, WireStyle},
+    IDENTITY,
 };
 
 pub const BEGIN_DRAG: Selector<Point> = Selector::new("logicism/begin-drag");
 pub const DESELECT_ALL: Selector<WidgetId> = Selector::new("logicism/deselect-all");
-pub const BEGIN_WIRE_DRAW: Selector<()> = Selector::new("logicism/begin-wire-draw");
+/// Start drawing a wire from the pin at the given coordinates
+pub const BEGIN_WIRE_DRAW: Selector<Coords> = Selector::new("logicism/begin-wire-draw");
+/// Copy the component with the given id and start dragging the copy from the given window position
+pub const BEGIN_DUPLICATE_DRAG: Selector<(ItemId, Point)> =
+    Selector::new("logicism/begin-duplicate-drag");
+/// Let clocked components take in what's on their inputs, once the circuit has changed
+const SETTLE: Selector = Selector::new("logicism/settle");
+
+// Layers painted with `paint_with_z_index`, bottom to top. Wires and then component bodies are
+// painted directly, so they go under all of these
+pub const PIN_LAYER: u32 = 1;
+pub const SELECTION_LAYER: u32 = 2;
+/// The placement ghost, wires being drawn and tooltips
+const OVERLAY_LAYER: u32 = 3;
+
+/// How far the canvas can be zoomed out and in
+const MIN_ZOOM: f64 = 0.25;
+const MAX_ZOOM: f64 = 4.0;
+/// How much one notch of ctrl+wheel zooms by
+const ZOOM_STEP: f64 = 1.25;
+/// Room left around the selection when zooming to it, in canvas space
+const ZOOM_TO_SELECTION_MARGIN: f64 = 32.0;
+/// Opacity of the fill inside the selection box, so the grid shows through
+const SELECTION_BOX_ALPHA: f64 = 0.2;
+
+/// How many cells apart the grid coordinate labels are
+const COORDINATE_LABEL_INTERVAL: usize = 5;
+/// Grid cells a paste is moved right and down by when the mouse isn't over the canvas, or when
+/// it would land on the last copy
+const PASTE_OFFSET: isize = 2;
+/// Grid cells dragged components can be pulled by to line their pins up with other pins
+const PIN_SNAP_DISTANCE: isize = 1;
+/// How long a message about something that went wrong stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(4);
 
 static NEXT_ITEM_ID: AtomicUsize = AtomicUsize::new(0);
 
-#[derive(Clone, Copy, Data, Debug, PartialEq, Eq)]
+/// Identifies a component or wire for as long as it exists, however it's moved or reordered. Ids
+/// are never reused, and copies get new ones
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ItemId(usize);
+
+impl ItemId {
+    pub fn next() -> Self {
+        ItemId(NEXT_ITEM_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, Hash)]
 pub struct Coords {
     pub x: isize,
     pub y: isize,
@@ -34,8 +101,10 @@ impl Coords {
         }
     }
 
+    /// Multiplies as floats, since far out coordinates would overflow as integers. Going the other
+    /// way, float to integer casts saturate
     pub fn to_widget_space(&self) -> Point {
-        Point::new((self.x * 16) as f64, (self.y * 16) as f64)
+        Point::new(self.x as f64 * 16.0, self.y as f64 * 16.0)
     }
 
     pub fn from_canvas_space(pos: Point) -> Self {
@@ -48,6 +117,22 @@ impl Coords {
     pub fn to_canvas_space(&self) -> Point {
         self.to_widget_space() + Vec2::new(8.0, 8.0)
     }
+
+    /// Where something at these coordinates ends up when the mouse drags it from one point to
+    /// another. The drag is rounded to whole cells before it's added, so everything dragged
+    /// together moves by exactly the same amount, wherever it is on the grid
+    pub fn dragged(&self, from: Point, to: Point) -> Self {
+        let delta = Coords::from_widget_space((to - from).to_point());
+        Coords::new(self.x + delta.x, self.y + delta.y)
+    }
+}
+
+/// A component found under a point on the canvas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub component: ItemId,
+    /// The index of the pin under the point in the component type's pin list, if there is one
+    pub pin: Option<usize>,
 }
 
 #[derive(Clone, Data)]
@@ -56,14 +141,42 @@ pub enum Tool {
     Place(Rc<ComponentType>, Orientation),
 }
 
+/// How a wire dragged out of a pin gets to where the mouse is released
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq)]
+pub enum Routing {
+    /// A single segment, snapped to the nearest compass direction
+    Straight,
+    /// Bends once to reach the mouse, like wires connected by clicking pins
+    Orthogonal,
+    /// A single segment like `Straight`, but also snapping to the diagonals
+    Diagonal,
+}
+
 #[derive(Clone, Data)]
 pub struct CanvasState {
-    wires: im::OrdMap<usize, WireState>,
-    components: im::OrdMap<usize, ComponentState>,
+    wires: im::OrdMap<ItemId, WireState>,
+    components: im::OrdMap<ItemId, ComponentState>,
+    /// Every component, in the order they're painted in, so later ones are on top
+    z_order: im::Vector<ItemId>,
     tool: Tool,
     mouse_pos: Option<Coords>,
     last_orientation: Orientation,
     drawing: Option<Coords>,
+    /// The pin that was clicked first when connecting two pins by clicking them in turn
+    connecting: Option<Coords>,
+    routing: Routing,
+    /// Whether every pin is labelled with the number of wires ending on it
+    show_connection_counts: bool,
+    /// Hides pin markers for a cleaner look, overriding the theme
+    hide_pins: bool,
+    /// Whether grid coordinates are labelled along the bottom and right edges
+    show_coordinates: bool,
+    /// Whether the list of key bindings is shown over the canvas
+    show_help: bool,
+    /// How many screen pixels a pixel of canvas space takes up
+    zoom: f64,
+    /// Where the origin of canvas space is on screen
+    pan: Vec2,
 }
 
 impl CanvasState {
@@ -71,28 +184,999 @@ impl CanvasState {
         CanvasState {
             wires: im::OrdMap::new(),
             components: im::OrdMap::new(),
+            z_order: im::Vector::new(),
             tool: Tool::Hand,
             mouse_pos: None,
             last_orientation: Orientation::North,
             drawing: None,
+            connecting: None,
+            routing: Routing::Straight,
+            show_connection_counts: false,
+            hide_pins: false,
+            show_coordinates: false,
+            show_help: false,
+            zoom: 1.0,
+            pan: Vec2::ZERO,
+        }
+    }
+
+    /// Takes canvas space to the canvas widget's own coordinates, which are the window's
+    fn view(&self) -> Affine {
+        Affine::translate(self.pan) * Affine::scale(self.zoom)
+    }
+
+    /// The part of canvas space that's on screen, given the size of the canvas widget
+    fn visible_rect(&self, size: Size) -> Rect {
+        self.view().inverse().transform_rect_bbox(size.to_rect())
+    }
+
+    /// Zooms by a factor, keeping the point under `screen_pos` where it is
+    fn zoom_about(&mut self, screen_pos: Point, factor: f64) {
+        let canvas_pos = self.view().inverse() * screen_pos;
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = screen_pos.to_vec2() - canvas_pos.to_vec2() * self.zoom;
+    }
+
+    /// Zooms and pans so the selected components and wires, or all of them if nothing is selected,
+    /// fill a canvas widget of the given size, as far as the zoom goes
+    fn zoom_to_selection(&mut self, size: Size) {
+        let rects = |selected_only: bool| -> Vec<Rect> {
+            let components = self
+                .components
+                .values()
+                .filter(|state| !selected_only || state.is_selected())
+                .map(|state| state.instance.bounding_rect());
+            let wires = self
+                .wires
+                .values()
+                .filter(|state| !selected_only || state.selected)
+                .map(|state| state.bounding_rect());
+            components.chain(wires).collect()
+        };
+        let selected = rects(true);
+        let rects = if selected.is_empty() {
+            rects(false)
+        } else {
+            selected
+        };
+        let bounds = match rects.into_iter().reduce(|a, b| a.union(b)) {
+            Some(bounds) => bounds.inflate(ZOOM_TO_SELECTION_MARGIN, ZOOM_TO_SELECTION_MARGIN),
+            None => return,
+        };
+        self.zoom = (size.width / bounds.width())
+            .min(size.height / bounds.height())
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan = size.to_rect().center().to_vec2() - bounds.center().to_vec2() * self.zoom;
+    }
+
+    /// The topmost component under a point in canvas space, and which of its pins the point is on.
+    /// Canvas space is the window's logical pixels at 1× zoom before any panning. Pins count
+    /// within `PIN_HIT_RADIUS` of their centre, which reaches just outside the component's body
+    pub fn hit_test(&self, pos: Point) -> Option<Hit> {
+        self.z_order.iter().rev().find_map(|id| {
+            let state = &self.components[id];
+            let pin = state.instance.pin_at_canvas_point(pos);
+            if pin.is_some() || state.instance.contains_point(pos) {
+                Some(Hit {
+                    component: *id,
+                    pin,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Whether a wire ending at `end` would connect to a pin there, rather than end in empty space
+    /// or be refused for tying two outputs together
+    fn will_connect(&self, start: Coords, end: Coords) -> bool {
+        start != end
+            && self.connection_refusal(start, end).is_none()
+            && self
+                .components
+                .values()
+                .any(|state| state.instance.pin_type_at(end).is_some())
+    }
+
+    /// Why a wire from `start` to a pin at `end` would be refused, if it would be
+    fn connection_refusal(&self, start: Coords, end: Coords) -> Option<&'static str> {
+        if start != end && self.connects_outputs(start, end) {
+            Some("Two outputs can't be tied together")
+        } else {
+            None
+        }
+    }
+
+    /// The selected components and wires, along with the wires running between the selected
+    /// components' pins. Selected wires attached to components that aren't selected are left out,
+    /// since they'd be left hanging
+    fn selection_netlist(&self) -> Netlist {
+        let selected: Vec<_> = self
+            .components
+            .values()
+            .filter(|state| state.is_selected())
+            .map(|state| state.instance.clone())
+            .collect();
+        let on_selected_pin = |coords| {
+            selected
+                .iter()
+                .any(|instance| instance.pin_type_at(coords).is_some())
+        };
+        let leaves_selection = |end: &WireEnd| {
+            matches!(end, WireEnd::Pin(id, _)
+                if self.components.get(id).filter(|state| state.is_selected()).is_none())
+        };
+        let wires = self
+            .wires
+            .values()
+            .filter(|wire| {
+                (wire.selected && !leaves_selection(&wire.start) && !leaves_selection(&wire.end))
+                    || match (wire.segments.front(), wire.segments.back()) {
+                        (Some(first), Some(last)) => {
+                            on_selected_pin(first.start()) && on_selected_pin(last.end())
+                        },
+                        _ => false,
+                    }
+            })
+            .cloned()
+            .collect();
+        Netlist {
+            components: selected,
+            wires,
+        }
+    }
+
+    /// The whole circuit, with components in the order they're painted in
+    fn netlist(&self) -> Netlist {
+        Netlist {
+            components: self
+                .z_order
+                .iter()
+                .map(|id| self.components[id].instance.clone())
+                .collect(),
+            wires: self.wires.values().cloned().collect(),
+        }
+    }
+
+    fn circuit(&self) -> Circuit {
+        Circuit {
+            components: self.components.clone(),
+            wires: self.wires.clone(),
+            z_order: self.z_order.clone(),
+        }
+    }
+
+    /// Attaches a wire end at these coordinates to the pin there, if there is one
+    fn wire_end_at(&self, coords: Coords) -> WireEnd {
+        self.components
+            .iter()
+            .find_map(|(id, state)| {
+                let pin = state.instance.pin_index_at(coords)?;
+                Some(WireEnd::Pin(*id, pin))
+            })
+            .unwrap_or(WireEnd::Free(coords))
+    }
+
+    /// Where a wire end currently is, or `None` if it's attached to a pin that no longer exists
+    fn resolve_wire_end(&self, end: WireEnd) -> Option<Coords> {
+        match end {
+            WireEnd::Pin(id, pin) => self
+                .components
+                .get(&id)
+                .and_then(|state| state.instance.resolved_pins().nth(pin)),
+            WireEnd::Free(coords) => Some(coords),
         }
     }
+
+    /// Where both ends of every wire are, leaving out wires whose pin has gone
+    fn connections(&self) -> Vec<(Coords, Coords)> {
+        self.wires
+            .values()
+            .filter_map(|wire| {
+                Some((
+                    self.resolve_wire_end(wire.start)?,
+                    self.resolve_wire_end(wire.end)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// The level on every pin, found by simulating the whole circuit
+    fn simulate(&self) -> Result<HashMap<PinRef, bool>, SimError> {
+        let components = self
+            .components
+            .iter()
+            .map(|(id, state)| (*id, &state.instance));
+        sim::simulate(components, self.connections())
+    }
+
+    /// Lets clocked components take in what's on their inputs, see `sim::settle`. Only the
+    /// components that took in something different are touched, so a circuit that has already
+    /// settled stays the same. A circuit that never settles is left as it was
+    fn settle(&mut self) -> Result<(), SimError> {
+        let mut instances: HashMap<_, _> = self
+            .components
+            .iter()
+            .map(|(id, state)| (*id, state.instance.clone()))
+            .collect();
+        sim::settle(&mut instances, &self.connections(), sim::MAX_SETTLE_PASSES)?;
+        for (id, instance) in instances {
+            if !self.components[&id].instance.same(&instance) {
+                self.components.get_mut(&id).unwrap().instance = instance;
+            }
+        }
+        Ok(())
+    }
+
+    /// The truth table from the selected switches and push buttons to the selected LEDs, and to the outputs of
+    /// other selected components that aren't wired to anything. The rest of the circuit is
+    /// simulated as it is. Inputs and outputs are named after their type and coordinates, and go
+    /// top to bottom and then left to right
+    fn selection_truth_table(&self) -> Result<TruthTable, SimError> {
+        let mut selected: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_selected())
+            .collect();
+        selected.sort_by_key(|(_, state)| (state.instance.coords.y, state.instance.coords.x));
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for (id, state) in selected {
+            let instance = &state.instance;
+            let Coords { x, y } = instance.coords;
+            let name = format!("{} {} {}", instance.ty().name, x, y);
+            match instance.ty().function {
+                Some(Function::Switch) | Some(Function::Button) => inputs.push((name, *id)),
+                Some(Function::Light) => outputs.push((name, Probe::Light(*id))),
+                // sources like clocks and constants aren't anything the table is of
+                _ if instance.ty().input_count() > 0 => {
+                    for (pin, coords) in instance.resolved_pins().enumerate() {
+                        if instance.pin_type(pin) == PinType::Output
+                            && self.connection_count(coords) == 0
+                        {
+                            let name = format!("{} {}", name, instance.pin_name(pin));
+                            let pin = PinRef {
+                                component: *id,
+                                pin,
+                            };
+                            outputs.push((name, Probe::Pin(pin)));
+                        }
+                    }
+                },
+                _ => {},
+            }
+        }
+        let components = self
+            .components
+            .iter()
+            .map(|(id, state)| (*id, state.instance.clone()))
+            .collect();
+        sim::truth_table(components, &self.connections(), &inputs, &outputs)
+    }
+
+    /// The words held by each selected RAM as CSV, a line per address, with the address and the
+    /// word in hex. RAM is named like in truth tables and goes in the same order. `None` if no
+    /// RAM is selected
+    fn selection_memory(&self) -> Option<String> {
+        let mut selected: Vec<_> = self
+            .components
+            .values()
+            .filter(|state| state.is_selected())
+            .map(|state| &state.instance)
+            .filter(|instance| instance.ty().function == Some(Function::Ram))
+            .collect();
+        if selected.is_empty() {
+            return None;
+        }
+        selected.sort_by_key(|instance| (instance.coords.y, instance.coords.x));
+        let mut csv = "component,address,data\n".to_owned();
+        for instance in selected {
+            let Coords { x, y } = instance.coords;
+            let name = format!("{} {} {}", instance.ty().name, x, y);
+            let digits = instance.ty().output_count().div_ceil(4);
+            for (address, word) in instance.words().into_iter().enumerate() {
+                writeln!(
+                    csv,
+                    "{},{:x},{:0digits$x}",
+                    name,
+                    address,
+                    word,
+                    digits = digits
+                )
+                .unwrap();
+            }
+        }
+        Some(csv)
+    }
+
+    /// Halves the period of every selected clock, or doubles it, as long as it stays within
+    /// bounds
+    fn change_selected_periods(&mut self, faster: bool) {
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| {
+                state.is_selected() && state.instance.ty().function == Some(Function::Clock)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            let instance = &mut self.components.get_mut(&id).unwrap().instance;
+            let period = if faster {
+                instance.period / 2
+            } else {
+                instance.period * 2
+            };
+            if (MIN_CLOCK_PERIOD..=MAX_CLOCK_PERIOD).contains(&period) {
+                instance.period = period;
+            }
+        }
+    }
+
+    /// Switches off every switch that's on
+    fn reset_switches(&mut self) {
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| {
+                state.instance.ty().function == Some(Function::Switch) && state.instance.value
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.components.get_mut(&id).unwrap().instance.value = false;
+        }
+    }
+
+    /// Makes the selected clock dividers count one more or one fewer rising edge before flipping,
+    /// and the selected delay lines lag one more or one less step
+    fn change_selected_divisors(&mut self, more: bool) {
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| {
+                state.is_selected()
+                    && matches!(
+                        state.instance.ty().function,
+                        Some(Function::Divider) | Some(Function::Delay)
+                    )
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            let instance = &mut self.components.get_mut(&id).unwrap().instance;
+            let _ = instance.change_divisor(more) || instance.change_delay(more);
+        }
+    }
+
+    /// Makes the given components and wires the whole selection
+    fn select_only(&mut self, ids: &[ItemId]) {
+        let components: Vec<_> = self.components.keys().copied().collect();
+        for id in components {
+            let state = self.components.get_mut(&id).unwrap();
+            if ids.contains(&id) {
+                state.select();
+            } else {
+                state.deselect();
+            }
+        }
+        let wires: Vec<_> = self.wires.keys().copied().collect();
+        for id in wires {
+            self.wires.get_mut(&id).unwrap().selected = ids.contains(&id);
+        }
+    }
+
+    /// Adds every component that overlaps the rect, in canvas space, to the selection, or with
+    /// `whole` set only those wholly inside it
+    fn select_within(&mut self, rect: Rect, whole: bool) {
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| {
+                let bounds = state.instance.bounding_rect();
+                if whole {
+                    rect.union(bounds) == rect
+                } else {
+                    bounds.intersect(rect).area() > 0.0
+                }
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.components.get_mut(&id).unwrap().select();
+        }
+    }
+
+    /// Bounding boxes of every component, for routing wires around
+    fn component_rects(&self) -> Vec<Rect> {
+        self.components
+            .values()
+            .map(|state| state.instance.bounding_rect())
+            .collect()
+    }
+
+    /// The pins of components being dragged, and the pins of every other component
+    fn dragged_and_target_pins(&self) -> (Vec<Coords>, Vec<Coords>) {
+        let (dragged, targets): (Vec<_>, Vec<_>) = self
+            .components
+            .values()
+            .partition(|state| state.is_dragging());
+        let pins = |states: Vec<&ComponentState>| {
+            states
+                .iter()
+                .flat_map(|state| state.instance.resolved_pins())
+                .collect()
+        };
+        (pins(dragged), pins(targets))
+    }
+
+    /// Pulls the dragged components by up to `PIN_SNAP_DISTANCE` cells if that lands more of their
+    /// pins on other components' pins than where the mouse put them, as long as it's at least two
+    fn snap_dragged_pins(&mut self) {
+        let (dragged, targets) = self.dragged_and_target_pins();
+        if dragged.len() < 2 || targets.is_empty() {
+            return;
+        }
+        let aligned = |(dx, dy): (isize, isize)| {
+            dragged
+                .iter()
+                .filter(|pin| targets.contains(&Coords::new(pin.x + dx, pin.y + dy)))
+                .count()
+        };
+        let range = -PIN_SNAP_DISTANCE..=PIN_SNAP_DISTANCE;
+        // ties go to the smallest pull, so an already aligned component stays where it is
+        let best = range
+            .clone()
+            .flat_map(|dx| range.clone().map(move |dy| (dx, dy)))
+            .max_by_key(|&(dx, dy)| (aligned((dx, dy)), -(dx.abs() + dy.abs())))
+            .unwrap();
+        if best == (0, 0) || aligned(best) < 2 {
+            return;
+        }
+        let (dx, dy) = best;
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_dragging())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            let instance = &mut self.components.get_mut(&id).unwrap().instance;
+            instance.coords = Coords::new(instance.coords.x + dx, instance.coords.y + dy);
+        }
+    }
+
+    /// Moves the ends of wires attached to pins along with the pins
+    fn reroute_wires(&mut self) {
+        let obstacles = self.component_rects();
+        let ids: Vec<_> = self.wires.keys().copied().collect();
+        for id in ids {
+            let wire = &self.wires[&id];
+            let old_start = wire.segments.front().unwrap().start();
+            let old_end = wire.segments.back().unwrap().end();
+            let start = self.resolve_wire_end(wire.start);
+            let end = self.resolve_wire_end(wire.end);
+            if (start, end) == (Some(old_start), Some(old_end)) {
+                continue;
+            }
+            let wire = self.wires.get_mut(&id).unwrap();
+            // a wire whose pin has gone keeps that end where it was, and the end is left free
+            if start.is_none() {
+                wire.start = WireEnd::Free(old_start);
+            }
+            if end.is_none() {
+                wire.end = WireEnd::Free(old_end);
+            }
+            wire.reroute(
+                start.unwrap_or(old_start),
+                end.unwrap_or(old_end),
+                &obstacles,
+            );
+        }
+    }
+
+    /// Changes every selected component to the given type, keeping where they are and which way
+    /// they face. Says whether any of them had a different pin layout, so wires may have come loose
+    fn replace_selected_type(&mut self, ty: &Rc<ComponentType>) -> bool {
+        let ids: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_selected())
+            .map(|(id, _)| *id)
+            .collect();
+        let mut layouts_differ = false;
+        for id in ids {
+            let instance = &mut self.components.get_mut(&id).unwrap().instance;
+            layouts_differ |= !instance.set_ty(Rc::clone(ty));
+        }
+        layouts_differ
+    }
+
+    /// Changes how every selected wire is drawn
+    fn restyle_selected_wires(&mut self, restyle: impl Fn(&mut WireStyle)) {
+        let ids: Vec<_> = self
+            .wires
+            .iter()
+            .filter(|(_, wire)| wire.selected)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            restyle(&mut self.wires.get_mut(&id).unwrap().style);
+        }
+    }
+
+    /// Moves the selected components to the top or bottom of the z order, keeping their order
+    /// among themselves
+    fn restack_selection(&mut self, to_front: bool) {
+        let (selected, rest): (im::Vector<_>, im::Vector<_>) = self
+            .z_order
+            .iter()
+            .copied()
+            .partition(|id| self.components[id].is_selected());
+        self.z_order = if to_front {
+            rest + selected
+        } else {
+            selected + rest
+        };
+    }
+
+    /// Selects the components wired to selected ones. With `transitive`, keeps going until
+    /// everything connected to the selection is selected. Only wires attached to pins at both ends
+    /// join components
+    fn grow_selection(&mut self, transitive: bool) {
+        let links: Vec<_> = self
+            .wires
+            .values()
+            .filter_map(|wire| match (wire.start, wire.end) {
+                (WireEnd::Pin(a, _), WireEnd::Pin(b, _)) => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+        let mut selected: Vec<_> = self
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_selected())
+            .map(|(id, _)| *id)
+            .collect();
+        loop {
+            let newly_selected: Vec<_> = links
+                .iter()
+                .filter_map(
+                    |&(a, b)| match (selected.contains(&a), selected.contains(&b)) {
+                        (true, false) => Some(b),
+                        (false, true) => Some(a),
+                        _ => None,
+                    },
+                )
+                .collect();
+            if newly_selected.is_empty() {
+                break;
+            }
+            for id in newly_selected {
+                if !selected.contains(&id) {
+                    selected.push(id);
+                    if let Some(state) = self.components.get_mut(&id) {
+                        state.select();
+                    }
+                }
+            }
+            if !transitive {
+                break;
+            }
+        }
+    }
+
+    /// How many wire ends are on a pin. Free ends that happen to lie on it count too, since
+    /// they're connected all the same
+    /// Finishes the wire being drawn where the mouse was let go, and returns it if one can be drawn
+    /// there. Letting go on the pin it started from waits for a second pin to be clicked instead
+    fn finish_wire(&mut self) -> Option<WireState> {
+        let start = self.drawing.take()?;
+        let mouse_pos = self.mouse_pos?;
+        // the wire goes where it was shown going while it was drawn
+        let end = snapped_end(start, mouse_pos, self.routing);
+        if mouse_pos == start {
+            self.connecting = Some(start);
+            None
+        } else if self.connects_outputs(start, end) {
+            // refuse to short two outputs together
+            None
+        } else if self.routing == Routing::Orthogonal {
+            WireState::routed(start, end, &self.component_rects())
+        } else {
+            WireSegment::new(start, end).map(|segment| WireState::new(im::Vector::unit(segment)))
+        }
+    }
+
+    fn connection_count(&self, pin: Coords) -> usize {
+        self.wires
+            .values()
+            .flat_map(|wire| [wire.start, wire.end])
+            .filter(|end| self.resolve_wire_end(*end) == Some(pin))
+            .count()
+    }
+
+    /// Whether a wire between these two points would tie two outputs together. Disabled components
+    /// don't drive their outputs, so those don't count
+    fn connects_outputs(&self, a: Coords, b: Coords) -> bool {
+        let is_output = |coords| {
+            self.components.values().any(|state| {
+                state.instance.enabled
+                    && state.instance.pin_type_at(coords) == Some(PinType::Output)
+            })
+        };
+        is_output(a) && is_output(b)
+    }
 }
 
 pub struct Canvas {
     component_types: Rc<Vec<Rc<ComponentType>>>,
-    wires: BTreeMap<usize, WidgetPod<WireState, Wire>>,
-    components: BTreeMap<usize, WidgetPod<ComponentState, Component>>,
+    keymap: Rc<Keymap>,
+    wires: BTreeMap<ItemId, WidgetPod<WireState, Wire>>,
+    components: BTreeMap<ItemId, WidgetPod<ComponentState, Component>>,
+    /// What's under the mouse, whose description is shown below it
+    hovered: Option<Hit>,
+    history: History,
+    /// Where the mouse was on screen when it last moved while middle-dragging the canvas
+    panning: Option<Point>,
+    /// The text last pasted, where it would have gone, and where it went instead if that's where
+    /// it had been pasted before
+    last_paste: Option<(String, Coords, Coords)>,
+    /// The corner a selection box was started from and the one under the mouse, in canvas space
+    selection_box: Option<(Point, Point)>,
+    /// Something that went wrong, shown in the corner until its timer goes off
+    message: Option<(String, TimerToken)>,
+    /// The circuit's simulated pin levels, worked out again whenever components or wires change
+    levels: Rc<Result<HashMap<PinRef, bool>, SimError>>,
 }
 
 impl Canvas {
-    pub fn new(component_types: Rc<Vec<Rc<ComponentType>>>) -> Self {
+    pub fn new(component_types: Rc<Vec<Rc<ComponentType>>>, keymap: Rc<Keymap>) -> Self {
         Canvas {
             component_types,
+            keymap,
             wires: BTreeMap::new(),
             components: BTreeMap::new(),
+            hovered: None,
+            history: History::new(),
+            panning: None,
+            last_paste: None,
+            selection_box: None,
+            message: None,
+            levels: Rc::new(Ok(HashMap::new())),
         }
     }
+
+    /// Tells the user about something that went wrong, in place of whatever was said last
+    fn report(&mut self, ctx: &mut druid::EventCtx, message: String) {
+        self.message = Some((message, ctx.request_timer(MESSAGE_DURATION)));
+        ctx.request_paint();
+    }
+
+    /// Adds the circuit on the clipboard, with its top left corner at the mouse if it's over the
+    /// canvas, and makes it the selection. Pasting the same thing in the same place again puts
+    /// each copy a step further on from the last, rather than on top of it
+    fn paste(&mut self, ctx: &mut druid::EventCtx, data: &mut CanvasState) {
+        let text = match Application::global().clipboard().get_string() {
+            Some(text) => text,
+            None => return,
+        };
+        let mut netlist = match Netlist::from_text(&text, &self.component_types) {
+            Ok(netlist) => netlist,
+            Err(e) => {
+                self.report(ctx, format!("Couldn't paste: {}", e));
+                return;
+            },
+        };
+        let visible = data.visible_rect(ctx.size());
+        let on_canvas = |coords: &Coords| visible.contains(coords.to_canvas_space());
+        let target = match (data.mouse_pos.filter(on_canvas), netlist.top_left()) {
+            (Some(mouse_pos), _) => Some(mouse_pos),
+            // without somewhere to put it, step it away from where it was copied from so the copy
+            // can be told apart from the original
+            (None, Some(Coords { x, y })) => Some(Coords::new(x + PASTE_OFFSET, y + PASTE_OFFSET)),
+            (None, None) => None,
+        };
+        if let Some(target) = target {
+            let placed = match self.last_paste {
+                Some((ref last_text, last_target, last_placed))
+                    if *last_text == text && last_target == target =>
+                {
+                    Coords::new(last_placed.x + PASTE_OFFSET, last_placed.y + PASTE_OFFSET)
+                },
+                _ => target,
+            };
+            netlist.move_to(placed);
+            self.last_paste = Some((text, target, placed));
+        }
+        let added = self.add_netlist(ctx, data, netlist);
+        // the copy replaces the selection, so it can be dragged away straight after
+        data.select_only(&added);
+        ctx.request_focus();
+    }
+
+    /// Replaces the whole circuit with the one in a file, leaving it alone if the file can't be
+    /// loaded
+    fn load(&mut self, ctx: &mut druid::EventCtx, data: &mut CanvasState, path: &Path) {
+        let netlist = match Netlist::load_from_path(path, &self.component_types) {
+            Ok(netlist) => netlist,
+            Err(e) => {
+                self.report(ctx, format!("Couldn't load {}: {}", path.display(), e));
+                return;
+            },
+        };
+        self.components.clear();
+        self.wires.clear();
+        self.hovered = None;
+        data.components.clear();
+        data.wires.clear();
+        data.z_order.clear();
+        data.drawing = None;
+        data.connecting = None;
+        self.add_netlist(ctx, data, netlist);
+    }
+
+    /// Puts the circuit back the way it was in a snapshot from the undo history, with widgets for
+    /// whatever comes back and without the ones for whatever goes
+    fn restore(&mut self, ctx: &mut druid::EventCtx, data: &mut CanvasState, circuit: Circuit) {
+        data.components = circuit.components;
+        data.wires = circuit.wires;
+        data.z_order = circuit.z_order;
+        // the snapshot may have been taken part way through a drag
+        let ids: Vec<_> = data.components.keys().copied().collect();
+        for id in ids {
+            data.components.get_mut(&id).unwrap().end_drag();
+        }
+        let ids: Vec<_> = data.wires.keys().copied().collect();
+        for id in ids {
+            data.wires.get_mut(&id).unwrap().end_drag();
+        }
+        data.drawing = None;
+        data.connecting = None;
+        self.hovered = None;
+        self.components
+            .retain(|id, _| data.components.contains_key(id));
+        self.wires.retain(|id, _| data.wires.contains_key(id));
+        let keymap = &self.keymap;
+        for id in data.components.keys() {
+            self.components
+                .entry(*id)
+                .or_insert_with(|| WidgetPod::new(Component::new(*id, Rc::clone(keymap))));
+        }
+        for id in data.wires.keys() {
+            self.wires
+                .entry(*id)
+                .or_insert_with(|| WidgetPod::new(Wire));
+        }
+        ctx.children_changed();
+        ctx.request_layout();
+        ctx.request_paint();
+    }
+
+    /// Adds every component and wire in a netlist, where the netlist puts them. Returns the ids
+    /// they were given
+    fn add_netlist(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        data: &mut CanvasState,
+        netlist: Netlist,
+    ) -> Vec<ItemId> {
+        let mut added = Vec::new();
+        for instance in netlist.components {
+            let id = ItemId::next();
+            added.push(id);
+            self.components.insert(
+                id,
+                WidgetPod::new(Component::new(id, Rc::clone(&self.keymap))),
+            );
+            data.components
+                .insert(id, ComponentState::from_instance(instance));
+            data.z_order.push_back(id);
+        }
+        for wire in netlist.wires {
+            added.push(self.add_wire(ctx, data, wire));
+        }
+        ctx.children_changed();
+        ctx.request_paint();
+        added
+    }
+
+    /// If one of the component's inputs and one of its outputs both sit on the same straight stretch
+    /// of a wire, cuts the wire between them so that it runs through the component instead
+    fn splice_into_wire(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        data: &mut CanvasState,
+        component: ItemId,
+    ) {
+        let instance = &data.components[&component].instance;
+        let pins: Vec<_> = instance
+            .resolved_pins()
+            .enumerate()
+            .map(|(i, coords)| (coords, instance.pin_type(i)))
+            .collect();
+        let pin_on = |segment: &WireSegment, ty| {
+            pins.iter()
+                .find(|(coords, pin_ty)| *pin_ty == ty && segment.contains(*coords))
+                .map(|(coords, _)| *coords)
+        };
+        let found = data.wires.iter().find_map(|(id, wire)| {
+            wire.segments.iter().enumerate().find_map(|(i, segment)| {
+                let input = pin_on(segment, PinType::Input)?;
+                let output = pin_on(segment, PinType::Output)?;
+                Some((*id, i, input, output))
+            })
+        });
+        let (id, i, input, output) = match found {
+            Some(found) => found,
+            None => return,
+        };
+
+        let wire = data.wires.remove(&id).unwrap();
+        self.wires.remove(&id);
+        let segment = &wire.segments[i];
+        let (start, end) = (segment.start(), segment.end());
+        // whichever pin comes first along the segment is where the first half stops
+        let distance = |c: Coords| c.x.abs_diff(start.x) + c.y.abs_diff(start.y);
+        let (first, second) = if distance(input) < distance(output) {
+            (input, output)
+        } else {
+            (output, input)
+        };
+        let mut before = wire.segments.clone();
+        let mut after = before.split_off(i);
+        after.pop_front();
+        if first != start {
+            before.push_back(WireSegment::new(start, first).unwrap());
+        }
+        if second != end {
+            after.push_front(WireSegment::new(second, end).unwrap());
+        }
+        for segments in [before, after] {
+            if !segments.is_empty() {
+                let mut half = WireState::new(segments);
+                half.style = wire.style.clone();
+                self.add_wire(ctx, data, half);
+            }
+        }
+        ctx.children_changed();
+    }
+
+    /// Removes the wires that end on pins of selected components, leaving the components where
+    /// they are
+    fn disconnect_selection(&mut self, ctx: &mut druid::EventCtx, data: &mut CanvasState) {
+        let pins: Vec<_> = data
+            .components
+            .values()
+            .filter(|state| state.is_selected())
+            .flat_map(|state| state.instance.resolved_pins())
+            .collect();
+        let attached: Vec<_> = data
+            .wires
+            .iter()
+            .filter(|(_, wire)| {
+                [wire.start, wire.end]
+                    .iter()
+                    .filter_map(|end| data.resolve_wire_end(*end))
+                    .any(|end| pins.contains(&end))
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in attached.iter() {
+            data.wires.remove(id);
+            self.wires.remove(id);
+        }
+        if !attached.is_empty() {
+            ctx.children_changed();
+            ctx.request_paint();
+        }
+    }
+
+    /// Removes the selected components and wires, and the wires ending on the components' pins, all
+    /// at once
+    fn delete_selection(&mut self, ctx: &mut druid::EventCtx, data: &mut CanvasState) {
+        self.disconnect_selection(ctx, data);
+        let wires: Vec<_> = data
+            .wires
+            .iter()
+            .filter(|(_, wire)| wire.selected)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in wires.iter() {
+            data.wires.remove(id);
+            self.wires.remove(id);
+        }
+        let components: Vec<_> = data
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_selected())
+            .map(|(id, _)| *id)
+            .collect();
+        // the widgets go with them, and with the widgets whatever drag they were in
+        for id in components.iter() {
+            data.components.remove(id);
+            self.components.remove(id);
+        }
+        data.z_order.retain(|id| !components.contains(id));
+        self.hovered = self
+            .hovered
+            .filter(|hit| !components.contains(&hit.component));
+        if !wires.is_empty() || !components.is_empty() {
+            ctx.children_changed();
+            ctx.request_paint();
+        }
+    }
+
+    /// Resizes every selected component with `resize`, such as `ComponentInstance::resize`, which
+    /// gives gates one more input or one fewer. Components it can't resize are left as they are.
+    /// Wires on a pin that goes are removed with it, so none are left attached to a pin that
+    /// isn't there
+    fn resize_selected(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        data: &mut CanvasState,
+        resize: fn(&mut ComponentInstance, bool) -> Option<Vec<Option<usize>>>,
+        more: bool,
+    ) {
+        let ids: Vec<_> = data
+            .components
+            .iter()
+            .filter(|(_, state)| state.is_selected())
+            .map(|(id, _)| *id)
+            .collect();
+        let wire_ids: Vec<_> = data.wires.keys().copied().collect();
+        let mut removed = Vec::new();
+        let mut changed = false;
+        for id in ids {
+            let instance = &mut data.components.get_mut(&id).unwrap().instance;
+            let pins = match resize(instance, more) {
+                Some(pins) => pins,
+                None => continue,
+            };
+            changed = true;
+            for wire_id in wire_ids.iter() {
+                let wire = data.wires.get_mut(wire_id).unwrap();
+                for end in [&mut wire.start, &mut wire.end] {
+                    match end {
+                        WireEnd::Pin(on, pin) if *on == id => match pins[*pin] {
+                            Some(resized) => *pin = resized,
+                            None => removed.push(*wire_id),
+                        },
+                        _ => {},
+                    }
+                }
+            }
+        }
+        for id in removed.iter() {
+            data.wires.remove(id);
+            self.wires.remove(id);
+        }
+        if changed {
+            // the pins under the mouse have moved
+            self.hovered = None;
+            data.reroute_wires();
+            ctx.children_changed();
+            ctx.request_layout();
+            ctx.request_paint();
+        }
+    }
+
+    /// Adds a wire, attaching each end to the pin it lands on if there is one
+    fn add_wire(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        data: &mut CanvasState,
+        mut state: WireState,
+    ) -> ItemId {
+        // TODO: merge connected segments
+        state.start = data.wire_end_at(state.segments.front().unwrap().start());
+        state.end = data.wire_end_at(state.segments.back().unwrap().end());
+        let id = ItemId::next();
+        self.wires.insert(id, WidgetPod::new(Wire));
+        data.wires.insert(id, state);
+        ctx.children_changed();
+        id
+    }
 }
 
 impl Widget<CanvasState> for Canvas {
@@ -103,55 +1187,291 @@ impl Widget<CanvasState> for Canvas {
         data: &mut CanvasState,
         env: &druid::Env,
     ) {
-        for (id, widget) in self.wires.iter_mut() {
-            let state = data.wires.get_mut(id).unwrap();
-            widget.event(ctx, event, state, env);
+        // the help is dismissed by anything, and nothing else sees that key press or click
+        if data.show_help {
+            if let druid::Event::KeyDown(_) | druid::Event::MouseDown(_) = event {
+                data.show_help = false;
+                ctx.set_handled();
+                ctx.request_paint();
+                return;
+            }
+        }
+
+        // panning happens on screen, and nothing else sees the drag
+        if let Some(last) = self.panning {
+            match event {
+                druid::Event::MouseMove(ev) => {
+                    data.pan += ev.pos - last;
+                    self.panning = Some(ev.pos);
+                    ctx.request_paint();
+                    return;
+                },
+                druid::Event::MouseUp(ev) if ev.button == MouseButton::Middle => {
+                    self.panning = None;
+                    ctx.set_active(false);
+                    return;
+                },
+                _ => {},
+            }
+        }
+        let event = &to_canvas_space(event, data.view());
+
+        // whatever changed since the last input becomes an edit, so a drag is only recorded once
+        // it's been dropped and something else happens
+        if let druid::Event::MouseDown(_) | druid::Event::KeyDown(_) | druid::Event::Wheel(_) =
+            event
+        {
+            self.history.record(data.circuit());
         }
 
+        // components are painted over wires, so they get the first look at clicks
         for (id, widget) in self.components.iter_mut() {
             let state = data.components.get_mut(id).unwrap();
             widget.event(ctx, event, state, env);
         }
 
+        for (id, widget) in self.wires.iter_mut() {
+            let state = data.wires.get_mut(id).unwrap();
+            widget.event(ctx, event, state, env);
+        }
+
+        if let druid::Event::MouseMove(_) = event {
+            data.snap_dragged_pins();
+        }
+
+        // components may have moved, rotated or been mirrored
+        data.reroute_wires();
+
+        // children mark what they repaint as if there were no view transform, so only repainting
+        // everything is sure to cover it
+        if data.view() != IDENTITY {
+            ctx.request_paint();
+        }
+
+        if let druid::Event::MouseMove(ev) = event {
+            // the tooltip is painted outside the component, so the whole canvas needs repainting
+            let hovered = data.hit_test(ev.pos);
+            if hovered != self.hovered {
+                self.hovered = hovered;
+                ctx.request_paint();
+            }
+        }
+
+        // the selection box follows the mouse until it's let go
+        match (self.selection_box, event) {
+            (Some((start, _)), druid::Event::MouseMove(ev)) => {
+                self.selection_box = Some((start, ev.pos));
+                ctx.request_paint();
+            },
+            (Some((start, end)), druid::Event::MouseUp(ev)) if ev.button == MouseButton::Left => {
+                self.selection_box = None;
+                data.select_within(Rect::from_points(start, end), selects_whole(start, end));
+                ctx.set_active(false);
+                ctx.request_paint();
+                // nothing was dragged, so there's nothing to drop
+                return;
+            },
+            _ => {},
+        }
+
         if ctx.is_handled() {
             return;
         }
 
         use druid::keyboard_types::Key;
         use druid::Event::*;
+        if let KeyDown(key_event) = event {
+            match self.keymap.action(key_event) {
+                Some(Action::SelectNeighbours) => data.grow_selection(false),
+                Some(Action::SelectConnected) => data.grow_selection(true),
+                Some(Action::Disconnect) => self.disconnect_selection(ctx, data),
+                Some(Action::Delete) => self.delete_selection(ctx, data),
+                Some(Action::Copy) => {
+                    let text = data.selection_netlist().to_text();
+                    Application::global().clipboard().put_string(text);
+                },
+                Some(Action::CopyPinMap) => {
+                    let csv = data.selection_netlist().pin_map();
+                    Application::global().clipboard().put_string(csv);
+                },
+                Some(Action::Paste) => self.paste(ctx, data),
+                Some(Action::Undo) => {
+                    if let Some(circuit) = self.history.undo(data.circuit()) {
+                        self.restore(ctx, data, circuit);
+                    }
+                },
+                Some(Action::Redo) => {
+                    if let Some(circuit) = self.history.redo(data.circuit()) {
+                        self.restore(ctx, data, circuit);
+                    }
+                },
+                // the file is written or read once the dialog comes back with a path
+                Some(Action::Save) => {
+                    let options = FileDialogOptions::new()
+                        .allowed_types(vec![CIRCUIT_FILE_TYPE])
+                        .default_type(CIRCUIT_FILE_TYPE);
+                    ctx.submit_command(commands::SHOW_SAVE_PANEL.with(options));
+                },
+                Some(Action::Open) => {
+                    let options = FileDialogOptions::new().allowed_types(vec![CIRCUIT_FILE_TYPE]);
+                    ctx.submit_command(commands::SHOW_OPEN_PANEL.with(options));
+                },
+                Some(Action::SwapType(n)) => {
+                    if let Some(ty) = self.component_types.get(n) {
+                        if data.replace_selected_type(ty) {
+                            self.report(
+                                ctx,
+                                "Replaced components with a different pin layout, wires may have \
+                                 come loose"
+                                    .to_owned(),
+                            );
+                        }
+                        ctx.request_layout();
+                        ctx.request_paint();
+                    }
+                },
+                Some(Action::CopyTruthTable) => match data.selection_truth_table() {
+                    Ok(table) => Application::global().clipboard().put_string(table.to_csv()),
+                    Err(e) => self.report(ctx, format!("Couldn't make a truth table: {}", e)),
+                },
+                Some(Action::CopyMemory) => match data.selection_memory() {
+                    Some(csv) => Application::global().clipboard().put_string(csv),
+                    None => self.report(ctx, "Select some RAM to copy what it holds".to_owned()),
+                },
+                Some(Action::Increase) => {
+                    self.resize_selected(ctx, data, ComponentInstance::resize, true);
+                    data.change_selected_periods(true);
+                    data.change_selected_divisors(true);
+                },
+                Some(Action::Decrease) => {
+                    self.resize_selected(ctx, data, ComponentInstance::resize, false);
+                    data.change_selected_periods(false);
+                    data.change_selected_divisors(false);
+                },
+                Some(Action::WidenAddress) => {
+                    self.resize_selected(ctx, data, ComponentInstance::resize_address, true);
+                },
+                Some(Action::NarrowAddress) => {
+                    self.resize_selected(ctx, data, ComponentInstance::resize_address, false);
+                },
+                // all in one key press, so it's undone in one go
+                Some(Action::ResetSwitches) => data.reset_switches(),
+                Some(Action::CycleWireColor) => data.restyle_selected_wires(WireStyle::cycle_color),
+                Some(Action::ToggleDashed) => {
+                    data.restyle_selected_wires(|style| style.dashed = !style.dashed);
+                },
+                Some(Action::BringToFront) => {
+                    data.restack_selection(true);
+                    ctx.request_paint();
+                },
+                Some(Action::SendToBack) => {
+                    data.restack_selection(false);
+                    ctx.request_paint();
+                },
+                Some(Action::ZoomToSelection) => {
+                    data.zoom_to_selection(ctx.size());
+                    ctx.request_paint();
+                },
+                _ => {},
+            }
+        }
+
         match (event, &mut data.tool) {
             (WindowConnected, _) => ctx.request_focus(),
+            (Timer(token), _) if self.message.as_ref().map(|(_, t)| t) == Some(token) => {
+                self.message = None;
+                ctx.request_paint();
+            },
             (KeyDown(key_event), tool) => {
                 let mut new_tool = tool.clone();
-                match (&key_event.key, &*tool) {
-                    (Key::Character(ref s), _) if s == " " => new_tool = Tool::Hand,
+                match (&key_event.key, self.keymap.action(key_event), &*tool) {
+                    (_, Some(Action::HandTool), _) => new_tool = Tool::Hand,
+                    (_, Some(Action::ToggleRouting), _) => {
+                        data.routing = match data.routing {
+                            Routing::Straight => Routing::Orthogonal,
+                            Routing::Orthogonal => Routing::Diagonal,
+                            Routing::Diagonal => Routing::Straight,
+                        };
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::ToggleConnectionCounts), _) => {
+                        data.show_connection_counts = !data.show_connection_counts;
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::TogglePins), _) => {
+                        data.hide_pins = !data.hide_pins;
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::ToggleCoordinates), _) => {
+                        data.show_coordinates = !data.show_coordinates;
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::ResetView), _) => {
+                        data.zoom = 1.0;
+                        data.pan = Vec2::ZERO;
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::ShowHelp), _) => {
+                        data.show_help = true;
+                        ctx.request_paint();
+                    },
+                    (_, Some(Action::Cancel), _) => {
+                        let had_box = self.selection_box.take().is_some();
+                        if data.connecting.take().is_some() || had_box {
+                            ctx.request_paint();
+                        }
+                    },
                     // once again foiled by other languages existing
-                    (Key::Character(ref s), _)
-                        if s.len() == 1 && s.chars().next().unwrap().is_digit(10) =>
+                    (Key::Character(ref s), _, _)
+                        if s.len() == 1
+                            && s.chars().next().unwrap().is_digit(10)
+                            && !key_event.mods.ctrl() =>
                     {
-                        let n = u16::from_str_radix(&s, 10).unwrap().wrapping_sub(1) as usize;
+                        let mut n = component_number(s);
+                        // pressing the key again moves on to the type ten further along, since
+                        // there are more types than number keys
+                        if let Tool::Place(ref current, _) = *tool {
+                            let current = self
+                                .component_types
+                                .iter()
+                                .position(|ty| Rc::ptr_eq(ty, current));
+                            if let Some(current) = current.filter(|current| current % 10 == n) {
+                                n = current + 10;
+                                if n >= self.component_types.len() {
+                                    n %= 10;
+                                }
+                            }
+                        }
                         if n < self.component_types.len() {
-                            new_tool = Tool::Place(
-                                Rc::clone(&self.component_types[n]),
-                                data.last_orientation,
-                            );
+                            let ty = &self.component_types[n];
+                            let orientation = ty.allowed_orientation(data.last_orientation);
+                            new_tool = Tool::Place(Rc::clone(ty), orientation);
                         }
                     },
-                    (Key::Character(ref s), &Tool::Place(ref ty, _)) if s == "w" => {
-                        new_tool = Tool::Place(Rc::clone(&ty), Orientation::North)
-                    },
-                    (Key::Character(ref s), &Tool::Place(ref ty, _)) if s == "a" => {
-                        new_tool = Tool::Place(Rc::clone(&ty), Orientation::West)
-                    },
-                    (Key::Character(ref s), &Tool::Place(ref ty, _)) if s == "s" => {
-                        new_tool = Tool::Place(Rc::clone(&ty), Orientation::South)
+                    (_, Some(Action::Face(orientation)), &Tool::Place(ref ty, _))
+                        if ty.allows(orientation) =>
+                    {
+                        new_tool = Tool::Place(Rc::clone(&ty), orientation)
                     },
-                    (Key::Character(ref s), &Tool::Place(ref ty, _)) if s == "d" => {
-                        new_tool = Tool::Place(Rc::clone(&ty), Orientation::East)
+                    (_, Some(Action::RotateClockwise), &Tool::Place(ref ty, orientation)) => {
+                        new_tool = Tool::Place(Rc::clone(ty), ty.rotated(orientation, true))
                     },
+                    (
+                        _,
+                        Some(Action::RotateCounterClockwise),
+                        &Tool::Place(ref ty, orientation),
+                    ) => new_tool = Tool::Place(Rc::clone(ty), ty.rotated(orientation, false)),
                     _ => {},
                 }
                 if !Data::same(tool, &new_tool) {
+                    match new_tool {
+                        // the crosshair wins over the cursors components set while hovered
+                        Tool::Place(_, orientation) => {
+                            data.last_orientation = orientation;
+                            ctx.override_cursor(&Cursor::Crosshair);
+                        },
+                        Tool::Hand => ctx.clear_cursor(),
+                    }
                     *tool = new_tool;
                     ctx.request_paint();
                 }
@@ -160,7 +1480,7 @@ impl Widget<CanvasState> for Canvas {
                 let new_coords = Coords::from_canvas_space(m.pos);
                 if data.mouse_pos != Some(new_coords) {
                     data.mouse_pos = Some(new_coords);
-                    if data.drawing.is_some() {
+                    if data.drawing.is_some() || data.connecting.is_some() {
                         ctx.request_paint();
                     }
                 }
@@ -172,39 +1492,144 @@ impl Widget<CanvasState> for Canvas {
                     ctx.request_paint();
                 }
             },
+            // dragging from empty canvas selects everything in a box. with ctrl, the box adds to
+            // the selection
             (MouseDown(ev), Tool::Hand) if ev.button == MouseButton::Left => {
-                ctx.submit_command(DESELECT_ALL.with(ctx.widget_id()));
+                if !ev.mods.ctrl() {
+                    ctx.submit_command(DESELECT_ALL.with(ctx.widget_id()));
+                }
+                self.selection_box = Some((ev.pos, ev.pos));
+                ctx.set_active(true);
             },
-            (MouseUp(ev), Tool::Hand) if ev.button == MouseButton::Left => {
-                if let Some(wire_start) = data.drawing {
-                    if let Some(segment) = WireSegment::new(wire_start, data.mouse_pos.unwrap()) {
-                        // TODO: merge connected segments
-                        let id = NEXT_ITEM_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                        let widget = WidgetPod::new(Wire(id));
-                        self.wires.insert(id, widget);
-                        let state = WireState {
-                            segments: im::Vector::from(&[segment][..]),
-                        };
-                        data.wires.insert(id, state);
-                        ctx.children_changed();
+            (MouseDown(ev), _) if ev.button == MouseButton::Middle && data.drawing.is_none() => {
+                self.panning = Some(data.view() * ev.pos);
+                ctx.set_active(true);
+            },
+            // ctrl+wheel zooms towards the mouse, whatever the tool
+            (Wheel(ev), _) if ev.mods.ctrl() => {
+                let delta = ev.wheel_delta.y;
+                if delta != 0.0 {
+                    let factor = if delta < 0.0 {
+                        ZOOM_STEP
+                    } else {
+                        1.0 / ZOOM_STEP
+                    };
+                    data.zoom_about(data.view() * ev.pos, factor);
+                    ctx.request_paint();
+                }
+            },
+            // wires can also be drawn by middle dragging from a pin
+            (MouseUp(ev), Tool::Hand)
+                if ev.button == MouseButton::Left
+                    || (ev.button == MouseButton::Middle && data.drawing.is_some()) =>
+            {
+                if data.drawing.is_some() {
+                    if let Some(state) = data.finish_wire() {
+                        self.add_wire(ctx, data, state);
+                    }
+                    ctx.set_active(false);
+                    ctx.request_paint();
+                } else if ev.mods.shift() {
+                    // dropping components with shift held splices them into wires they sit on
+                    let selected: Vec<_> = data
+                        .components
+                        .iter()
+                        .filter(|(_, state)| state.is_selected())
+                        .map(|(id, _)| *id)
+                        .collect();
+                    for id in selected {
+                        self.splice_into_wire(ctx, data, id);
                     }
                 }
-                data.drawing = None;
+            },
+            // the wheel turns the component about to be placed, the same way it turns selected
+            // ones
+            (Wheel(ev), Tool::Place(ty, orientation)) if !ev.mods.ctrl() => {
+                let delta = if ev.wheel_delta.y != 0.0 {
+                    ev.wheel_delta.y
+                } else {
+                    ev.wheel_delta.x
+                };
+                if delta != 0.0 {
+                    *orientation = ty.rotated(*orientation, (delta > 0.0) != ev.mods.shift());
+                    data.last_orientation = *orientation;
+                    ctx.request_paint();
+                }
             },
             (MouseDown(ev), Tool::Place(ty, orientation)) if ev.button == MouseButton::Left => {
                 let coords = Coords::from_canvas_space(ev.pos);
-                let id = NEXT_ITEM_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                self.components.insert(id, WidgetPod::new(Component(id)));
-                data.components.insert(
+                // holding shift places the component facing north regardless of the tool
+                let orientation = if ev.mods.shift() {
+                    ty.allowed_orientation(Orientation::North)
+                } else {
+                    *orientation
+                };
+                let id = ItemId::next();
+                self.components.insert(
                     id,
-                    ComponentState::new(coords, Rc::clone(&ty), *orientation),
+                    WidgetPod::new(Component::new(id, Rc::clone(&self.keymap))),
                 );
+                data.components
+                    .insert(id, ComponentState::new(coords, Rc::clone(&ty), orientation));
+                data.z_order.push_back(id);
                 ctx.children_changed();
                 ctx.request_paint();
             },
+            (Command(c), _) if c.is(BEGIN_DUPLICATE_DRAG) => {
+                let (original, window_pos) = *c.get(BEGIN_DUPLICATE_DRAG).unwrap();
+                if let Some(state) = data
+                    .components
+                    .get(&original)
+                    .map(ComponentState::duplicate)
+                {
+                    let id = ItemId::next();
+                    let widget = WidgetPod::new(Component::new(id, Rc::clone(&self.keymap)));
+                    let widget_id = widget.id();
+                    self.components.insert(id, widget);
+                    data.components.insert(id, state);
+                    data.z_order.push_back(id);
+                    ctx.children_changed();
+                    ctx.submit_command(DESELECT_ALL.with(widget_id));
+                    ctx.submit_command(BEGIN_DRAG.with(window_pos));
+                }
+            },
+            (Command(c), _) if c.is(commands::SAVE_FILE_AS) => {
+                let path = c.get(commands::SAVE_FILE_AS).unwrap().path();
+                if let Err(e) = data.netlist().save_to_path(path) {
+                    self.report(ctx, format!("Couldn't save {}: {}", path.display(), e));
+                }
+            },
+            (Command(c), _) if c.is(SETTLE) => {
+                // shown along with the other reasons the circuit can't be simulated
+                if let Err(e) = data.settle() {
+                    self.levels = Rc::new(Err(e));
+                    ctx.request_paint();
+                }
+            },
+            (Command(c), _) if c.is(commands::OPEN_FILE) => {
+                let path = c.get(commands::OPEN_FILE).unwrap().path().to_owned();
+                self.load(ctx, data, &path);
+            },
             (Command(c), _) if c.is(BEGIN_WIRE_DRAW) => {
-                let widget_idx = c.get(BEGIN_WIRE_DRAW).unwrap();
-                data.drawing = Some(data.mouse_pos.unwrap());
+                let pin = *c.get(BEGIN_WIRE_DRAW).unwrap();
+                if let Some(first) = data.connecting {
+                    // outputs can't be tied together, so wait for a pin that can be connected.
+                    // clicking the first pin again routes to nothing and just cancels
+                    if first == pin || !data.connects_outputs(first, pin) {
+                        data.connecting = None;
+                        if let Some(state) = WireState::routed(first, pin, &data.component_rects())
+                        {
+                            self.add_wire(ctx, data, state);
+                        }
+                        ctx.request_paint();
+                    }
+                } else {
+                    // the press that started the wire mustn't also start a selection box, or
+                    // letting go would select rather than finish the wire
+                    self.selection_box = None;
+                    data.drawing = Some(pin);
+                    ctx.set_active(true);
+                }
             },
             _ => {},
         }
@@ -221,7 +1646,10 @@ impl Widget<CanvasState> for Canvas {
         match event {
             // TODO: doesn't seem to do anything, why??? instead we use WindowConnected event
             // handler
-            WidgetAdded => ctx.register_for_focus(),
+            WidgetAdded => {
+                ctx.register_for_focus();
+                self.levels = Rc::new(data.simulate());
+            },
             _ => {},
         }
 
@@ -241,6 +1669,19 @@ impl Widget<CanvasState> for Canvas {
         data: &CanvasState,
         env: &druid::Env,
     ) {
+        // any change to the circuit can change what the LEDs show
+        if !data.components.same(&old_data.components) || !data.wires.same(&old_data.wires) {
+            self.levels = Rc::new(data.simulate());
+            let clocked = data
+                .components
+                .values()
+                .any(|state| state.instance.ty().is_clocked());
+            if clocked {
+                ctx.submit_command(SETTLE.to(ctx.widget_id()));
+            }
+            ctx.request_paint();
+        }
+
         for (widget, new, old) in self
             .wires
             .iter_mut()
@@ -264,6 +1705,10 @@ impl Widget<CanvasState> for Canvas {
                 if !Data::same(&new.instance, &old.instance) {
                     ctx.request_layout();
                 }
+                // the outline around a multiple selection is painted by the canvas
+                if new.is_selected() != old.is_selected() {
+                    ctx.request_paint();
+                }
             }
         }
     }
@@ -289,62 +1734,671 @@ impl Widget<CanvasState> for Canvas {
     }
 
     fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &CanvasState, env: &druid::Env) {
-        let size = ctx.size();
+        let levels = Rc::clone(&self.levels);
+        let view = data.view();
+        ctx.with_save(|ctx| {
+            ctx.transform(view);
 
-        // dots
-        for x in 0..size.width as usize / 16 {
-            for y in 0..size.height as usize / 16 {
-                ctx.fill(
-                    Rect::from_center_size(
-                        Point::new(x as f64 * 16.0 + 8.0, y as f64 * 16.0 + 8.0),
-                        Size::new(2.0, 2.0),
-                    ),
-                    &Color::GRAY,
-                );
+            // dots, only in the part of the canvas being repainted
+            let invalid = view
+                .inverse()
+                .transform_rect_bbox(ctx.region().bounding_box());
+            let first = Coords::from_canvas_space(invalid.origin());
+            let last = Coords::from_canvas_space(Point::new(invalid.x1, invalid.y1));
+            let mut dots = BezPath::new();
+            for x in first.x..=last.x {
+                for y in first.y..=last.y {
+                    let center = Coords::new(x, y).to_canvas_space();
+                    let dot = Rect::from_center_size(center, Size::new(2.0, 2.0));
+                    dots.extend(dot.path_elements(0.0));
+                }
             }
+            ctx.fill(dots, &Color::GRAY);
+
+            // the region children are culled against isn't in canvas space, so they're always
+            // painted
+            for (widget, data) in self.wires.values_mut().zip(data.wires.values()) {
+                widget.paint_always(ctx, data, env);
+            }
+
+            let component_env = if data.hide_pins {
+                env.clone().adding(SHOW_PINS, false)
+            } else {
+                env.clone()
+            };
+            for id in data.z_order.iter() {
+                let mut state = &data.components[id];
+                // LEDs show the level on their input, which takes the whole circuit to work out
+                let lit;
+                if state.instance.ty().function == Some(Function::Light) {
+                    let mut copy = state.clone();
+                    copy.instance.value = match *levels {
+                        Ok(ref levels) => sim::output(levels, *id, &state.instance),
+                        Err(_) => false,
+                    };
+                    lit = copy;
+                    state = &lit;
+                }
+                self.components
+                    .get_mut(id)
+                    .unwrap()
+                    .paint_always(ctx, state, &component_env);
+            }
+        });
+
+        let data = data.clone();
+        let hovered = self.hovered;
+        let selection_box = self.selection_box;
+        let help = self.keymap.describe();
+        let message = self.message.as_ref().map(|(message, _)| message.clone());
+        let env = env.clone();
+        ctx.paint_with_z_index(OVERLAY_LAYER, move |ctx| {
+            ctx.with_save(|ctx| {
+                ctx.transform(data.view());
+                paint_overlays(ctx, &data, &levels, hovered, &env);
+                if let Some((start, end)) = selection_box {
+                    let rect = Rect::from_points(start, end);
+                    let color = env.get(SELECTION_COLOR);
+                    ctx.fill(rect, &color.clone().with_alpha(SELECTION_BOX_ALPHA));
+                    // dashed when it only takes what's wholly inside
+                    if selects_whole(start, end) {
+                        let dashes = StrokeStyle::new().dash(vec![4.0, 4.0], 0.0);
+                        ctx.stroke_styled(rect, &color, 1.0, &dashes);
+                    } else {
+                        ctx.stroke(rect, &color, 1.0);
+                    }
+                }
+            });
+            paint_screen_overlays(ctx, &data, &levels, message.as_deref(), &env);
+            if data.show_help {
+                paint_help(ctx, &help, &env);
+            }
+        });
+    }
+}
+
+/// Where a wire being drawn from `start` ends with the mouse at `mouse_pos`. Straight wires snap
+/// to the nearest compass direction, and diagonal ones to the nearest of those or a diagonal.
+/// Bent wires go wherever the mouse is
+fn snapped_end(start: Coords, mouse_pos: Coords, routing: Routing) -> Coords {
+    let mut end = mouse_pos;
+    let (dx, dy) = (end.x.abs_diff(start.x), end.y.abs_diff(start.y));
+    match routing {
+        Routing::Orthogonal => {},
+        // closer to a diagonal than to either axis
+        Routing::Diagonal if dx < dy * 2 && dy < dx * 2 => {
+            let d = dx.max(dy) as isize;
+            end.x = start.x + d * (mouse_pos.x - start.x).signum();
+            end.y = start.y + d * (mouse_pos.y - start.y).signum();
+        },
+        _ if dx > dy => end.y = start.y,
+        _ => end.x = start.x,
+    }
+    end
+}
+
+/// Whether a selection box dragged out from `start` to `end` only takes what's wholly inside it,
+/// which it does when dragged leftwards, rather than everything it touches
+fn selects_whole(start: Point, end: Point) -> bool {
+    end.x < start.x
+}
+
+/// The event as the circuit sees it, with mouse positions in canvas space. Components and wires
+/// take window positions to be in canvas space too, so those are moved the same way
+fn to_canvas_space(event: &druid::Event, view: Affine) -> druid::Event {
+    use druid::Event::*;
+    let inverse = view.inverse();
+    let transform = |ev: &MouseEvent| {
+        let mut ev = ev.clone();
+        ev.pos = inverse * ev.pos;
+        ev.window_pos = inverse * ev.window_pos;
+        ev
+    };
+    match event {
+        MouseDown(ev) => MouseDown(transform(ev)),
+        MouseUp(ev) => MouseUp(transform(ev)),
+        MouseMove(ev) => MouseMove(transform(ev)),
+        Wheel(ev) => Wheel(transform(ev)),
+        event => event.clone(),
+    }
+}
+
+/// Which component type a number key picks. 0 comes after 9, the way the keys are laid out
+fn component_number(digit: &str) -> usize {
+    (digit.parse::<usize>().unwrap() + 9) % 10
+}
+
+/// Everything that stays put on screen as the canvas is panned and zoomed
+fn paint_screen_overlays(
+    ctx: &mut druid::PaintCtx,
+    data: &CanvasState,
+    levels: &Result<HashMap<PinRef, bool>, SimError>,
+    message: Option<&str>,
+    env: &druid::Env,
+) {
+    // grid coordinates, every few cells so that the labels don't run into each other
+    if data.show_coordinates {
+        let size = ctx.size();
+        let visible = data.visible_rect(size);
+        let first = Coords::from_canvas_space(visible.origin());
+        let last = Coords::from_canvas_space(Point::new(visible.x1, visible.y1));
+        // labels go on multiples of the interval, wherever the view starts
+        let interval = COORDINATE_LABEL_INTERVAL as isize;
+        let round_up = |n: isize| n + (-n).rem_euclid(interval);
+        let label = |ctx: &mut druid::PaintCtx, n: isize| {
+            ctx.text()
+                .new_text_layout(n.to_string())
+                .font(FontFamily::SYSTEM_UI, 9.0)
+                .text_color(env.get(theme::PLACEHOLDER_COLOR))
+                .build()
+                .unwrap()
+        };
+        for x in (round_up(first.x)..=last.x).step_by(COORDINATE_LABEL_INTERVAL) {
+            let layout = label(ctx, x);
+            let pos = data.view() * Coords::new(x, 0).to_canvas_space();
+            let origin = Point::new(pos.x - layout.size().width / 2.0, size.height - 12.0);
+            ctx.draw_text(&layout, origin);
         }
+        for y in (round_up(first.y)..=last.y).step_by(COORDINATE_LABEL_INTERVAL) {
+            let layout = label(ctx, y);
+            let pos = data.view() * Coords::new(0, y).to_canvas_space();
+            let origin = Point::new(
+                size.width - layout.size().width - 2.0,
+                pos.y - layout.size().height / 2.0,
+            );
+            ctx.draw_text(&layout, origin);
+        }
+    }
+
+    // routing mode
+    let routing = match data.routing {
+        Routing::Straight => "Wires: straight",
+        Routing::Orthogonal => "Wires: bent",
+        Routing::Diagonal => "Wires: diagonal",
+    };
+    let layout = ctx
+        .text()
+        .new_text_layout(routing)
+        .font(FontFamily::SYSTEM_UI, 12.0)
+        .text_color(env.get(theme::LABEL_COLOR))
+        .build()
+        .unwrap();
+    ctx.draw_text(&layout, Point::new(8.0, 8.0));
+
+    // the simulation only fails as a whole, so that's said once rather than on every pin, and
+    // anything else that went wrong goes under it
+    let problems = levels
+        .as_ref()
+        .err()
+        .map(|e| format!("Can't simulate: {}", e))
+        .into_iter()
+        .chain(message.map(str::to_owned));
+    for (i, problem) in problems.enumerate() {
+        let layout = ctx
+            .text()
+            .new_text_layout(problem)
+            .font(FontFamily::SYSTEM_UI, 12.0)
+            .text_color(env.get(REFUSED_TARGET_COLOR))
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, Point::new(8.0, 24.0 + 16.0 * i as f64));
+    }
+}
 
-        // cursor ghost
-        if let Tool::Place(ref ty, orientation) = data.tool {
-            if let Some(c) = data.mouse_pos {
-                let component = ComponentInstance::new(c, Rc::clone(&ty), orientation);
-                ctx.with_save(|ctx| {
-                    ctx.transform(Affine::translate(
-                        component.bounding_rect().origin() - Point::ORIGIN,
-                    ));
-                    component.paint(ctx);
-                });
+/// Everything that goes above the circuit itself, in canvas space
+fn paint_overlays(
+    ctx: &mut druid::PaintCtx,
+    data: &CanvasState,
+    levels: &Result<HashMap<PinRef, bool>, SimError>,
+    hovered: Option<Hit>,
+    env: &druid::Env,
+) {
+    // extent of a multiple selection
+    let mut selected = data
+        .components
+        .values()
+        .filter(|state| state.is_selected())
+        .map(|state| state.instance.bounding_rect());
+    if let (Some(first), Some(second)) = (selected.next(), selected.next()) {
+        let extent = selected.fold(first.union(second), |a, b| a.union(b));
+        let dashes = StrokeStyle::new().dash(vec![4.0, 4.0], 0.0);
+        let color = env.get(SECONDARY_SELECTION_COLOR);
+        ctx.stroke_styled(extent.inflate(8.0, 8.0), &color, 1.0, &dashes);
+    }
+
+    // the clocked components that were still changing when the circuit gave up settling
+    if let Err(SimError::Oscillating(ids)) = levels {
+        let color = env.get(REFUSED_TARGET_COLOR);
+        for state in ids.iter().filter_map(|id| data.components.get(id)) {
+            ctx.stroke(
+                state.instance.bounding_rect().inflate(4.0, 4.0),
+                &color,
+                2.0,
+            );
+        }
+    }
+
+    // guides from the pins of dragged components to the other pins they line up with
+    let (dragged, targets) = data.dragged_and_target_pins();
+    if !targets.is_empty() {
+        let dashes = StrokeStyle::new().dash(vec![2.0, 3.0], 0.0);
+        let color = env.get(SECONDARY_SELECTION_COLOR);
+        for pin in dragged {
+            if targets.contains(&pin) {
+                let ring = Circle::new(pin.to_canvas_space(), 5.0);
+                ctx.fill(ring, &color.clone().with_alpha(0.3));
+                ctx.stroke(ring, &color, 1.5);
+                continue;
+            }
+            // only the nearest pin in each direction, so a long row of pins doesn't make a fan
+            let nearest = |aligned: &dyn Fn(&Coords) -> bool| {
+                targets
+                    .iter()
+                    .filter(|target| aligned(target))
+                    .min_by_key(|target| target.x.abs_diff(pin.x) + target.y.abs_diff(pin.y))
+                    .copied()
+            };
+            let guides = [nearest(&|t| t.x == pin.x), nearest(&|t| t.y == pin.y)];
+            for target in guides.iter().flatten() {
+                let line = druid::kurbo::Line::new(pin.to_canvas_space(), target.to_canvas_space());
+                ctx.stroke_styled(line, &color, 1.0, &dashes);
             }
         }
+    }
 
-        // drawing wire
-        if let Some(drawing) = data.drawing {
-            // snap start->mouse_pos line to compass directions
-            let mut mouse_pos = data.mouse_pos.unwrap();
-            let is_horizontal_draw =
-                mouse_pos.x.abs_diff(drawing.x) > mouse_pos.y.abs_diff(drawing.y);
-            if is_horizontal_draw {
-                mouse_pos.y = drawing.y;
-            } else {
-                mouse_pos.x = drawing.x;
+    // wire count on every pin
+    if data.show_connection_counts {
+        for state in data.components.values() {
+            let pins = state.instance.resolved_pins();
+            for (pin, no_connect) in pins.zip(state.instance.no_connect.iter()) {
+                let count = data.connection_count(pin);
+                // pins marked no-connect are meant to be floating
+                let color = if count == 0 && !no_connect {
+                    env.get(CONNECT_TARGET_COLOR)
+                } else {
+                    env.get(theme::LABEL_COLOR)
+                };
+                let layout = ctx
+                    .text()
+                    .new_text_layout(count.to_string())
+                    .font(FontFamily::SYSTEM_UI, 9.0)
+                    .text_color(color)
+                    .build()
+                    .unwrap();
+                ctx.draw_text(&layout, pin.to_canvas_space() + Vec2::new(3.0, -12.0));
             }
+        }
+    }
 
-            // unwrap: since we just snapped mouse_pos, it can't be None
-            let segment = WireSegment::new(drawing, mouse_pos).unwrap();
+    // cursor ghost
+    if let Tool::Place(ref ty, orientation) = data.tool {
+        if let Some(c) = data.mouse_pos {
+            let component = ComponentInstance::new(c, Rc::clone(&ty), orientation);
             ctx.with_save(|ctx| {
                 ctx.transform(Affine::translate(
-                    segment.bounding_rect().origin() - Point::ORIGIN,
+                    component.bounding_rect().origin() - Point::ORIGIN,
                 ));
-                segment.paint(ctx);
+                component.paint(ctx, env);
+                component.paint_pins(ctx);
             });
         }
+    }
 
-        for (widget, data) in self.wires.values_mut().zip(data.wires.values()) {
-            widget.paint(ctx, data, env);
+    // drawing wire
+    match (data.drawing, data.mouse_pos, data.routing) {
+        (Some(drawing), Some(mouse_pos), Routing::Straight | Routing::Diagonal) => {
+            let end = snapped_end(drawing, mouse_pos, data.routing);
+            if let Some(segment) = WireSegment::new(drawing, end) {
+                segment.paint(ctx, &WireStyle::default(), env);
+                paint_connect_target(ctx, data, drawing, end, env);
+            }
+        },
+        (Some(drawing), Some(mouse_pos), Routing::Orthogonal) => {
+            if let Some(route) = WireState::routed(drawing, mouse_pos, &data.component_rects()) {
+                for segment in route.segments.iter() {
+                    segment.paint(ctx, &route.style, env);
+                }
+            }
+            paint_connect_target(ctx, data, drawing, mouse_pos, env);
+        },
+        _ => {},
+    }
+
+    // pending click-to-connect wire
+    if let Some(first) = data.connecting {
+        ctx.stroke(Circle::new(first.to_canvas_space(), 5.0), &Color::AQUA, 1.0);
+        if let Some(route) = data
+            .mouse_pos
+            .and_then(|end| WireState::routed(first, end, &data.component_rects()))
+        {
+            for segment in route.segments.iter() {
+                segment.paint(ctx, &route.style, env);
+            }
         }
+        if let Some(end) = data.mouse_pos {
+            paint_connect_target(ctx, data, first, end, env);
+        }
+    }
 
-        for (widget, data) in self.components.values_mut().zip(data.components.values()) {
-            widget.paint(ctx, data, env);
+    // description tooltip
+    if let Some((hit, instance)) = hovered.and_then(|hit| {
+        let state = data.components.get(&hit.component)?;
+        Some((hit, &state.instance))
+    }) {
+        let mut description = instance.ty().description.clone();
+        if instance.ty().function == Some(Function::Clock) {
+            write!(description, ", every {} ms", instance.period.as_millis()).unwrap();
+            if instance.paused {
+                description.push_str(", paused");
+            }
         }
+        let pin = hit.pin;
+        let description = match pin.map(|pin| (instance.pin_type(pin), instance.pin_name(pin))) {
+            Some((PinType::Input, name)) => format!("{} (input {})", description, name),
+            Some((PinType::Output, name)) => format!("{} (output {})", description, name),
+            None => description.clone(),
+        };
+        let level = pin.and_then(|pin| {
+            let levels = levels.as_ref().ok()?;
+            levels.get(&PinRef {
+                component: hit.component,
+                pin,
+            })
+        });
+        let description = match level {
+            Some(high) => format!("{}, currently {}", description, *high as u8),
+            None => description,
+        };
+        // what and where it is above that, to tell apart components that look alike
+        let Coords { x, y } = instance.coords;
+        let description = format!(
+            "{} at {} {}, facing {}\n{}",
+            instance.ty().name,
+            x,
+            y,
+            instance.orientation,
+            description
+        );
+        let layout = ctx
+            .text()
+            .new_text_layout(description)
+            .font(FontFamily::SYSTEM_UI, 12.0)
+            .text_color(env.get(theme::LABEL_COLOR))
+            .build()
+            .unwrap();
+        let rect = instance.bounding_rect();
+        let origin = Point::new(rect.x0, rect.y1 + 8.0);
+        let background = Rect::from_origin_size(origin, layout.size()).inflate(3.0, 2.0);
+        ctx.fill(background, &env.get(theme::BACKGROUND_LIGHT));
+        ctx.stroke(background, &env.get(theme::BORDER_DARK), 1.0);
+        ctx.draw_text(&layout, origin);
+    }
+}
+
+/// A box in the middle of the canvas listing key bindings
+fn paint_help(ctx: &mut druid::PaintCtx, bindings: &[(&'static str, String)], env: &druid::Env) {
+    let build = |ctx: &mut druid::PaintCtx, text: String| {
+        ctx.text()
+            .new_text_layout(text)
+            .font(FontFamily::SYSTEM_UI, 12.0)
+            .text_color(env.get(theme::LABEL_COLOR))
+            .build()
+            .unwrap()
+    };
+    let names = bindings
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let keys = bindings
+        .iter()
+        .map(|(_, key)| key.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let names = build(ctx, names);
+    let keys = build(ctx, keys);
+    let size = Size::new(
+        names.size().width + 24.0 + keys.size().width,
+        names.size().height.max(keys.size().height),
+    );
+    let origin = ctx.size().to_rect().center() - size.to_vec2() / 2.0;
+    let background = Rect::from_origin_size(origin, size).inflate(12.0, 12.0);
+    ctx.fill(background, &env.get(theme::BACKGROUND_LIGHT));
+    ctx.stroke(background, &env.get(theme::BORDER_DARK), 1.0);
+    ctx.draw_text(&names, origin);
+    ctx.draw_text(&keys, origin + Vec2::new(names.size().width + 24.0, 0.0));
+}
+
+/// Marks the pin a wire will connect to if the mouse is released or clicked, or says why it won't
+fn paint_connect_target(
+    ctx: &mut druid::PaintCtx,
+    data: &CanvasState,
+    start: Coords,
+    end: Coords,
+    env: &druid::Env,
+) {
+    let ring = Circle::new(end.to_canvas_space(), 6.0);
+    if data.will_connect(start, end) {
+        let color = env.get(CONNECT_TARGET_COLOR);
+        ctx.fill(ring, &color.clone().with_alpha(0.3));
+        ctx.stroke(ring, &color, 2.0);
+    } else if let Some(reason) = data.connection_refusal(start, end) {
+        let color = env.get(REFUSED_TARGET_COLOR);
+        ctx.fill(ring, &color.clone().with_alpha(0.3));
+        ctx.stroke(ring, &color, 2.0);
+        let layout = ctx
+            .text()
+            .new_text_layout(reason)
+            .font(FontFamily::SYSTEM_UI, 12.0)
+            .text_color(color)
+            .build()
+            .unwrap();
+        ctx.draw_text(&layout, end.to_canvas_space() + Vec2::new(10.0, -6.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_out_coordinates_dont_overflow() {
+        for &n in &[isize::MIN, isize::MAX] {
+            let point = Coords::new(n, n).to_canvas_space();
+            assert!(point.x.is_finite() && point.y.is_finite());
+            assert_eq!(point.x.signum(), n.signum() as f64);
+        }
+        // float to integer casts saturate rather than wrapping
+        assert_eq!(
+            Coords::from_canvas_space(Point::new(f64::MAX, f64::MIN)),
+            Coords::new(isize::MAX, isize::MIN)
+        );
+        // anything a netlist can put far out still comes back to the same cell
+        let far = Coords::new(1 << 24, -(1 << 24));
+        assert_eq!(Coords::from_canvas_space(far.to_canvas_space()), far);
+    }
+
+    /// A canvas with a gate at each of the given coordinates, and their ids in the same order
+    fn canvas_of_gates(coords: &[Coords]) -> (CanvasState, Vec<ItemId>) {
+        let ty = ComponentType::enumerate()
+            .into_iter()
+            .find(|ty| ty.name == "and")
+            .unwrap();
+        let mut data = CanvasState::new();
+        let ids = coords
+            .iter()
+            .map(|coords| {
+                let id = ItemId::next();
+                let state = ComponentState::new(*coords, Rc::clone(&ty), Orientation::North);
+                data.components.insert(id, state);
+                data.z_order.push_back(id);
+                id
+            })
+            .collect();
+        (data, ids)
+    }
+
+    #[test]
+    fn box_selects_what_it_surrounds() {
+        let coords = [
+            Coords::new(0, 0),
+            Coords::new(5, 0),
+            Coords::new(10, 3),
+            Coords::new(30, 0),
+        ];
+        let (mut data, ids) = canvas_of_gates(&coords);
+        let around_three = data.components[&ids[0]]
+            .instance
+            .bounding_rect()
+            .union(data.components[&ids[2]].instance.bounding_rect())
+            .inflate(4.0, 4.0);
+        data.select_within(around_three, false);
+        let selected: Vec<_> = ids
+            .iter()
+            .map(|id| data.components[id].is_selected())
+            .collect();
+        assert_eq!(selected, [true, true, true, false]);
+
+        // in a row with the middle two a cell out of line, only the ends are wholly inside a box
+        // around them
+        let row = [
+            Coords::new(0, 0),
+            Coords::new(5, 1),
+            Coords::new(10, -1),
+            Coords::new(30, 0),
+        ];
+        let (mut data, ids) = canvas_of_gates(&row);
+        let first = data.components[&ids[0]].instance.bounding_rect();
+        let last = data.components[&ids[3]].instance.bounding_rect();
+        let through = Rect::new(first.x0, first.y0, last.x1, first.y1);
+        data.select_within(through, true);
+        let selected: Vec<_> = ids
+            .iter()
+            .map(|id| data.components[id].is_selected())
+            .collect();
+        assert_eq!(selected, [true, false, false, true]);
+    }
+
+    #[test]
+    fn zooming_to_the_selection_frames_it() {
+        let coords = [Coords::new(0, 0), Coords::new(5, 2), Coords::new(60, 30)];
+        let (mut data, ids) = canvas_of_gates(&coords);
+        let size = Size::new(800.0, 600.0);
+        let rect = |data: &CanvasState, id: &ItemId| data.components[id].instance.bounding_rect();
+        let on_screen = |data: &CanvasState, id: &ItemId| {
+            let visible = data.visible_rect(size);
+            visible.union(rect(data, id)) == visible
+        };
+
+        for id in ids[..2].iter() {
+            data.components.get_mut(id).unwrap().select();
+        }
+        data.zoom_to_selection(size);
+        assert!(ids[..2].iter().all(|id| on_screen(&data, id)));
+        assert!(!on_screen(&data, &ids[2]));
+        // zoomed in as far as it goes, which still leaves room around them
+        assert_eq!(data.zoom, MAX_ZOOM);
+
+        // everything once nothing is selected
+        data.select_only(&[]);
+        data.zoom_to_selection(size);
+        assert!(ids.iter().all(|id| on_screen(&data, id)));
+        assert!(data.zoom < 1.0);
+    }
+
+    #[test]
+    fn dragged_items_move_together() {
+        let starts = [Coords::new(0, 0), Coords::new(5, -3), Coords::new(-7, 12)];
+        let (mut data, ids) = canvas_of_gates(&starts);
+        // every selected component is told where the drag started, and follows the mouse from
+        // there. part way into a cell, rounding each one's own position could go either way
+        let (from, to) = (Point::new(13.0, 5.0), Point::new(51.0, -20.0));
+        for id in ids.iter() {
+            let instance = &mut data.components.get_mut(id).unwrap().instance;
+            instance.coords = instance.coords.dragged(from, to);
+        }
+        for (id, start) in ids.iter().zip(starts.iter()) {
+            let end = data.components[id].instance.coords;
+            assert_eq!((end.x - start.x, end.y - start.y), (2, -2));
+        }
+    }
+
+    #[test]
+    fn resetting_switches_is_one_edit_that_leaves_clocks_alone() {
+        let mut data = CanvasState::new();
+        let types = ComponentType::enumerate();
+        let mut add = |name: &str, x: isize| {
+            let ty = types.iter().find(|ty| ty.name == name).unwrap();
+            let mut state =
+                ComponentState::new(Coords::new(x, 0), Rc::clone(ty), Orientation::North);
+            state.instance.value = true;
+            let id = ItemId::next();
+            data.components.insert(id, state);
+            data.z_order.push_back(id);
+            id
+        };
+        let switches = [add("switch", 0), add("switch", 4)];
+        let clock = add("clock", 8);
+        let vcc = add("vcc", 12);
+        let values = |data: &CanvasState, ids: &[ItemId]| -> Vec<bool> {
+            ids.iter()
+                .map(|id| data.components[id].instance.value)
+                .collect()
+        };
+
+        let mut history = History::new();
+        history.record(data.circuit());
+        data.reset_switches();
+        assert_eq!(values(&data, &switches), [false, false]);
+        assert_eq!(values(&data, &[clock, vcc]), [true, true]);
+
+        let undone = history.undo(data.circuit()).unwrap();
+        let values_after_undo: Vec<_> = switches
+            .iter()
+            .map(|id| undone.components[id].instance.value)
+            .collect();
+        assert_eq!(values_after_undo, [true, true]);
+        assert!(history.undo(undone).is_none());
+    }
+
+    #[test]
+    fn dragging_from_pin_to_pin_draws_a_wire() {
+        // the second gate's A input is straight above the first gate's output
+        let (mut data, ids) = canvas_of_gates(&[Coords::new(0, 0), Coords::new(1, -6)]);
+        let pin = |data: &CanvasState, id: ItemId, name: &str| {
+            let instance = &data.components[&id].instance;
+            let index = instance.pin_named(name).unwrap();
+            instance.resolved_pins().nth(index).unwrap()
+        };
+        let (from, to) = (pin(&data, ids[0], "Y"), pin(&data, ids[1], "A"));
+        assert!(matches!(data.tool, Tool::Hand));
+        // pressing on a pin starts drawing, and the mouse is let go over the other pin
+        data.drawing = Some(from);
+        data.mouse_pos = Some(to);
+        let wire = data.finish_wire().unwrap();
+        assert_eq!(wire.segments.front().unwrap().start(), from);
+        assert_eq!(wire.segments.back().unwrap().end(), to);
+        assert_eq!((data.drawing, data.connecting), (None, None));
+
+        // letting go a little off the line still ends the wire on it, as it was drawn
+        data.drawing = Some(from);
+        data.mouse_pos = Some(Coords::new(to.x + 1, to.y));
+        let wire = data.finish_wire().unwrap();
+        assert_eq!(wire.segments.back().unwrap().end(), to);
+        data.routing = Routing::Diagonal;
+        data.drawing = Some(from);
+        data.mouse_pos = Some(Coords::new(from.x + 4, from.y - 5));
+        let wire = data.finish_wire().unwrap();
+        assert_eq!(
+            wire.segments.back().unwrap().end(),
+            Coords::new(from.x + 5, from.y - 5)
+        );
+        data.routing = Routing::Straight;
+
+        // letting go on the same pin waits for a second one to be clicked
+        data.drawing = Some(from);
+        data.mouse_pos = Some(from);
+        assert!(data.finish_wire().is_none());
+        assert_eq!(data.connecting, Some(from));
     }
 }
@@ -1,16 +1,69 @@
-use std::{rc::Rc, str::FromStr};
+use std::{rc::Rc, str::FromStr, time::Duration};
 
 use druid::{
-    kurbo::RoundedRect, widget::SvgData, Affine, Color, Data, Event, Insets, PaintCtx, Point, Rect,
-    RenderContext, Size, Vec2, Widget,
+    im,
+    kurbo::{BezPath, Circle, Line, RoundedRect, Shape},
+    piet::{Text, TextLayout, TextLayoutBuilder},
+    theme,
+    widget::SvgData,
+    Affine, Color, Cursor, Data, Env, Event, FontFamily, Insets, MouseButton, PaintCtx, Point,
+    Rect, RenderContext, Size, TimerToken, Vec2, Widget,
 };
 
 use crate::{
-    canvas::{Coords, BEGIN_DRAG, BEGIN_WIRE_DRAW, DESELECT_ALL},
+    canvas::{
+        Coords, ItemId, BEGIN_DRAG, BEGIN_DUPLICATE_DRAG, BEGIN_WIRE_DRAW, DESELECT_ALL, PIN_LAYER,
+        SELECTION_LAYER,
+    },
+    keymap::{Action, Keymap},
+    theme::{
+        ANIMATE_ROTATION, FOCUS_RING_COLOR, HIGH_LEVEL_COLOR, HOVERED_PIN_COLOR, LOW_LEVEL_COLOR,
+        PIN_STUB_LENGTH, SECONDARY_SELECTION_COLOR, SELECTION_COLOR, SHOW_PINS,
+        SHOW_PIN_DIRECTIONS,
+    },
     IDENTITY,
 };
 
-#[derive(Clone, Copy, Data, PartialEq, Eq)]
+/// Opacity of a component that is disabled or not being simulated
+const DISABLED_ALPHA: f64 = 0.4;
+/// How far from a pin's centre the mouse still counts as being on it, in pixels
+pub const PIN_HIT_RADIUS: f64 = 4.0;
+/// How long pins stay highlighted after rotating a component with the keyboard
+const ROTATION_PREVIEW_DURATION: Duration = Duration::from_millis(500);
+/// How long the icon takes to turn to a new orientation
+const ROTATION_ANIMATION_DURATION: Duration = Duration::from_millis(100);
+/// The fewest inputs a gate can have. Gate icons are drawn for this many
+pub const MIN_GATE_INPUTS: usize = 2;
+const MAX_GATE_INPUTS: usize = 8;
+/// Names for the inputs of gates, in order along the input edge
+const INPUT_NAMES: [&str; MAX_GATE_INPUTS] = ["A", "B", "C", "D", "E", "F", "G", "H"];
+/// How long a new clock stays high or low before flipping
+pub const DEFAULT_CLOCK_PERIOD: Duration = Duration::from_millis(500);
+/// How short and long a clock's period can be made, and be loaded from a netlist
+pub const MIN_CLOCK_PERIOD: Duration = Duration::from_millis(50);
+pub const MAX_CLOCK_PERIOD: Duration = Duration::from_secs(8);
+/// How many bits a new register or RAM word has, and the most it can have
+pub const DEFAULT_WORD_WIDTH: usize = 4;
+const MAX_WORD_WIDTH: usize = 8;
+/// Names for the data inputs of registers and RAM and the outputs they go to, in order along
+/// their edges
+const DATA_INPUT_NAMES: [&str; MAX_WORD_WIDTH] = ["D0", "D1", "D2", "D3", "D4", "D5", "D6", "D7"];
+const DATA_OUTPUT_NAMES: [&str; MAX_WORD_WIDTH] = ["Q0", "Q1", "Q2", "Q3", "Q4", "Q5", "Q6", "Q7"];
+/// How many address bits a new RAM has, and the most it can have
+pub const DEFAULT_ADDRESS_WIDTH: usize = 4;
+const MAX_ADDRESS_WIDTH: usize = 8;
+/// Names for the address inputs of RAM, from the lowest bit up
+const ADDRESS_NAMES: [&str; MAX_ADDRESS_WIDTH] = ["A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7"];
+/// How many times CLK rises before a new clock divider flips Q, and the most it can be made
+pub const DEFAULT_DIVISOR: usize = 2;
+pub const MAX_DIVISOR: usize = 16;
+/// Bits a clock divider counts rising edges in, enough to count up to one less than the most
+const DIVIDER_COUNT_BITS: usize = 4;
+/// How many settling passes a new delay line's Y lags behind A, and the most it can be made to
+pub const DEFAULT_DELAY: usize = 4;
+pub const MAX_DELAY: usize = 16;
+
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, Hash)]
 pub enum Orientation {
     North,
     East,
@@ -19,6 +72,13 @@ pub enum Orientation {
 }
 
 impl Orientation {
+    pub const ALL: [Orientation; 4] = [
+        Orientation::North,
+        Orientation::East,
+        Orientation::South,
+        Orientation::West,
+    ];
+
     pub fn angle(&self) -> f64 {
         match self {
             Orientation::North => 0.0,
@@ -27,143 +87,1508 @@ impl Orientation {
             Orientation::West => std::f64::consts::FRAC_PI_2 * 3.0,
         }
     }
+
+    /// The orientation at a position in `ALL`, wrapping around, so adding one turns clockwise
+    pub fn from_index(index: u8) -> Self {
+        Orientation::ALL[index as usize % 4]
+    }
+
+    pub fn rotated_cw(self) -> Self {
+        Orientation::from_index(self as u8 + 1)
+    }
+
+    pub fn rotated_ccw(self) -> Self {
+        Orientation::from_index(self as u8 + 3)
+    }
+}
+
+/// The names used in netlists, like `north`
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Orientation::North => "north",
+            Orientation::East => "east",
+            Orientation::South => "south",
+            Orientation::West => "west",
+        })
+    }
 }
 
-#[derive(Debug)]
-enum PinType {
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Orientation::ALL
+            .iter()
+            .copied()
+            .find(|orientation| orientation.to_string() == s)
+            .ok_or_else(|| format!("unknown orientation `{}`", s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinType {
     Input,
     Output,
 }
 
-#[derive(Debug)]
+/// The boolean function a component's outputs compute from its inputs, taken in pin list order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Buffer,
+    Not,
+    And,
+    Or,
+    Nand,
+    Nor,
+    /// High when an odd number of inputs are
+    Xor,
+    Xnor,
+    /// Like xor, high when an odd number of inputs are, so that along with the output an even
+    /// number are high
+    EvenParity,
+    /// Like xnor, so that along with the output an odd number of inputs are high
+    OddParity,
+    Zero,
+    One,
+    /// Outputs whatever the component is switched to
+    Switch,
+    /// Outputs high while it's held down with the mouse, and low once it's let go
+    Button,
+    /// Outputs whatever level the clock has got to. It flips by itself on a timer
+    Clock,
+    /// Has no outputs. What it evaluates to is whether the light is on
+    Light,
+    /// Outputs what it holds, which it takes from D when CLK goes high. S and R, if it has them,
+    /// set and reset it straight away, with R winning if both are high
+    FlipFlop,
+    /// Each Q output gives the bit the register holds for it. When CLK goes high while EN is,
+    /// it takes the bits on the D inputs
+    Register,
+    /// The Q outputs give the word at the address on the A inputs, or with clocked reads the word
+    /// that was there when CLK last went high. When CLK goes high while WE is, the bits on the D
+    /// inputs are written to that address
+    Ram,
+    /// Outputs what it holds, which flips every `ComponentInstance::divisor` times CLK goes high
+    Divider,
+    /// Outputs what it holds, which is the level A had `ComponentInstance::delay` passes of
+    /// settling ago
+    Delay,
+}
+
+impl Function {
+    /// `value` is the component's own state, which switches, clocks and flip-flops use. Registers
+    /// and RAM give each output something different, see `ComponentInstance::word_outputs`
+    pub fn evaluate(self, inputs: &[bool], value: bool) -> bool {
+        let high = inputs.iter().filter(|input| **input).count();
+        match self {
+            Function::Buffer => high > 0,
+            Function::Not => high == 0,
+            Function::And => high == inputs.len(),
+            Function::Or => high > 0,
+            Function::Nand => high != inputs.len(),
+            Function::Nor => high == 0,
+            Function::Xor | Function::EvenParity => high % 2 == 1,
+            Function::Xnor | Function::OddParity => high % 2 == 0,
+            Function::Zero => false,
+            Function::One => true,
+            Function::Switch
+            | Function::Button
+            | Function::Clock
+            | Function::Divider
+            | Function::Delay => value,
+            Function::Light => high > 0,
+            Function::FlipFlop => {
+                let set = inputs.get(2) == Some(&true);
+                let reset = inputs.get(3) == Some(&true);
+                !reset && (set || value)
+            },
+            Function::Register | Function::Ram => {
+                unreachable!("each output of a register or RAM is a different bit")
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 struct Pin {
+    /// Position relative to the anchor, in whole grid cells. Rotating or mirroring about the anchor
+    /// only swaps and negates these, so pins always land on grid intersections
     pos: Coords,
     ty: PinType,
+    /// The pin's logical role, like `A` or `CLK`. It stays with the pin however the component is
+    /// rotated or mirrored
+    name: &'static str,
+    /// Inputs that are only taken in when the component is clocked, so the outputs don't follow
+    /// them straight away. Feeding an output back into one of these isn't a loop
+    clocked: bool,
 }
 
 impl Pin {
-    fn new(x: isize, y: isize, ty: PinType) -> Self {
+    fn new(name: &'static str, x: isize, y: isize, ty: PinType) -> Self {
         Pin {
             pos: Coords::new(x, y),
             ty,
+            name,
+            clocked: false,
         }
     }
 }
 
 pub struct ComponentType {
+    /// Identifies the type in netlists
+    pub name: String,
     pub size: Size,
     /// The point that is represented by the coordinates of a component when it is oriented north
     anchor_offset: Vec2,
     pub icon: SvgData,
+    /// The size the icon is drawn at. Gates with extra inputs are wider than their icon, which sits
+    /// in the middle of the input edge
+    icon_size: Size,
+    /// Whether the icon already draws an inversion bubble on the output. These can't have the bubble
+    /// toggled, since there's no way to hide the one in the icon
+    output_bubble: bool,
+    /// The boolean function of the output, shown when the component is hovered
+    pub description: String,
+    /// What the outputs compute when simulated. Types without one don't drive their outputs
+    pub function: Option<Function>,
     pins: Vec<Pin>,
+    /// The orientations components of this type can face, in clockwise order. Parts that only
+    /// make sense wide or tall can leave some out
+    orientations: Vec<Orientation>,
+    /// Whether components of the type can be given more inputs, see `with_inputs`
+    variable_inputs: bool,
 }
 
 impl ComponentType {
     pub fn enumerate() -> Vec<Rc<Self>> {
-        let not_gate = ComponentType {
-            size: Size::new(24.0, 48.0),
-            anchor_offset: Vec2::new(12.0, 32.0),
-            icon: SvgData::from_str(include_str!("../res/not_gate.svg")).unwrap(),
-            pins: vec![
-                Pin::new(0, 1, PinType::Input),
-                Pin::new(0, -2, PinType::Output),
-            ],
-        };
-        let and_gate = ComponentType {
-            size: Size::new(48.0, 48.0),
-            anchor_offset: Vec2::new(24.0, 32.0),
-            icon: SvgData::from_str(include_str!("../res/and_gate.svg")).unwrap(),
-            pins: vec![
-                Pin::new(-1, 1, PinType::Input),
-                Pin::new(1, 1, PinType::Input),
-                Pin::new(0, -2, PinType::Output),
-            ],
+        let gate = |name: &str, icon: &str, function: Function| {
+            ComponentTypeBuilder::new(name)
+                .size(48.0, 48.0)
+                .anchor(24.0, 32.0)
+                .icon_from_str(icon)
+                .description(&gate_description(function, &INPUT_NAMES[..MIN_GATE_INPUTS]))
+                .function(function)
+                .gate_pins(&INPUT_NAMES[..MIN_GATE_INPUTS], "Y")
+                .variable_inputs()
         };
-        let or_gate = ComponentType {
-            size: Size::new(48.0, 48.0),
-            anchor_offset: Vec2::new(24.0, 32.0),
-            icon: SvgData::from_str(include_str!("../res/or_gate.svg")).unwrap(),
-            pins: vec![
-                Pin::new(-1, 1, PinType::Input),
-                Pin::new(1, 1, PinType::Input),
-                Pin::new(0, -2, PinType::Output),
-            ],
-        };
-        let nand_gate = ComponentType {
-            size: Size::new(48.0, 48.0),
-            anchor_offset: Vec2::new(24.0, 32.0),
-            icon: SvgData::from_str(include_str!("../res/nand_gate.svg")).unwrap(),
-            pins: vec![
-                Pin::new(-1, 1, PinType::Input),
-                Pin::new(1, 1, PinType::Input),
-                Pin::new(0, -2, PinType::Output),
-            ],
+        let not_gate = ComponentTypeBuilder::new("not")
+            .size(24.0, 48.0)
+            .anchor(12.0, 32.0)
+            .icon_from_str(include_str!("../res/not_gate.svg"))
+            .output_bubble()
+            .description("Y = ¬A")
+            .function(Function::Not)
+            .gate_pins(&["A"], "Y");
+        let and_gate = gate("and", include_str!("../res/and_gate.svg"), Function::And);
+        let or_gate = gate("or", include_str!("../res/or_gate.svg"), Function::Or);
+        let nand_gate =
+            gate("nand", include_str!("../res/nand_gate.svg"), Function::Nand).output_bubble();
+        let gnd = ComponentTypeBuilder::new("gnd")
+            .size(24.0, 24.0)
+            .anchor(12.0, 0.0)
+            .icon_from_str(include_str!("../res/gnd.svg"))
+            .description("Y = 0")
+            .function(Function::Zero)
+            .output_pin("Y", 0, 0);
+        let vcc = ComponentTypeBuilder::new("vcc")
+            .size(24.0, 24.0)
+            .anchor(12.0, 24.0)
+            .icon_from_str(include_str!("../res/vcc.svg"))
+            .description("Y = 1")
+            .function(Function::One)
+            .output_pin("Y", 0, 0);
+        let buffer = ComponentTypeBuilder::new("buffer")
+            .size(24.0, 48.0)
+            .anchor(12.0, 32.0)
+            .icon_from_str(include_str!("../res/buffer.svg"))
+            .description("Y = A")
+            .function(Function::Buffer)
+            .gate_pins(&["A"], "Y");
+        let xor_gate = gate("xor", include_str!("../res/xor_gate.svg"), Function::Xor);
+        let nor_gate =
+            gate("nor", include_str!("../res/nor_gate.svg"), Function::Nor).output_bubble();
+        let xnor_gate =
+            gate("xnor", include_str!("../res/xnor_gate.svg"), Function::Xnor).output_bubble();
+        let even_parity = gate(
+            "parity-even",
+            include_str!("../res/parity_even.svg"),
+            Function::EvenParity,
+        );
+        let odd_parity = gate(
+            "parity-odd",
+            include_str!("../res/parity_odd.svg"),
+            Function::OddParity,
+        )
+        .output_bubble();
+        let switch = ComponentTypeBuilder::new("switch")
+            .size(24.0, 24.0)
+            .anchor(12.0, 0.0)
+            .icon_from_str(include_str!("../res/switch.svg"))
+            .description("Click to switch Y")
+            .function(Function::Switch)
+            .output_pin("Y", 0, 0);
+        let button = ComponentTypeBuilder::new("push-button")
+            .size(24.0, 24.0)
+            .anchor(12.0, 0.0)
+            .icon_from_str(include_str!("../res/button.svg"))
+            .description("Hold down to raise Y")
+            .function(Function::Button)
+            .output_pin("Y", 0, 0);
+        let led = ComponentTypeBuilder::new("led")
+            .size(24.0, 24.0)
+            .anchor(12.0, 24.0)
+            .icon_from_str(include_str!("../res/led.svg"))
+            .description("Lit when A is")
+            .function(Function::Light)
+            .input_pin("A", 0, 0);
+        let clock = ComponentTypeBuilder::new("clock")
+            .size(24.0, 24.0)
+            .anchor(12.0, 0.0)
+            .icon_from_str(include_str!("../res/clock.svg"))
+            .description("Flips Y on a timer. Click to pause")
+            .function(Function::Clock)
+            .output_pin("Y", 0, 0);
+        let flip_flop = |name: &str, description: &str| {
+            ComponentTypeBuilder::new(name)
+                .size(64.0, 48.0)
+                .anchor(32.0, 32.0)
+                .icon_from_str(include_str!("../res/dff.svg"))
+                .description(description)
+                .function(Function::FlipFlop)
+                .clocked_input_pin("D", -1, 1)
+                .clocked_input_pin("CLK", 1, 1)
         };
+        let dff = flip_flop("dff", "Q takes D when CLK rises").output_pin("Q", 0, -2);
+        let dff_sr = flip_flop(
+            "dff-sr",
+            "Q takes D when CLK rises. S sets and R resets it straight away",
+        )
+        .input_pin("S", -2, -1)
+        .input_pin("R", 2, -1)
+        .output_pin("Q", 0, -2);
+        let divider = ComponentTypeBuilder::new("clock-divider")
+            .size(32.0, 48.0)
+            .anchor(16.0, 32.0)
+            .icon_from_str(include_str!("../res/divider.svg"))
+            .description("Flips Q every few times CLK rises. + and - change how many")
+            .function(Function::Divider)
+            .clocked_input_pin("CLK", 0, 1)
+            .output_pin("Q", 0, -2);
+        // A is clocked so that it's only taken in between passes of settling, which is what
+        // lets a delay line lag behind it
+        let delay = ComponentTypeBuilder::new("delay")
+            .size(32.0, 48.0)
+            .anchor(16.0, 32.0)
+            .icon_from_str(include_str!("../res/delay.svg"))
+            .description("Y follows A a few steps later. + and - change how many")
+            .function(Function::Delay)
+            .clocked_input_pin("A", 0, 1)
+            .output_pin("Y", 0, -2);
+        let register = register(DEFAULT_WORD_WIDTH);
+        let ram_sync = ram(DEFAULT_ADDRESS_WIDTH, DEFAULT_WORD_WIDTH, true);
+        let ram = ram(DEFAULT_ADDRESS_WIDTH, DEFAULT_WORD_WIDTH, false);
         vec![
-            Rc::new(not_gate),
-            Rc::new(and_gate),
-            Rc::new(or_gate),
-            Rc::new(nand_gate),
+            not_gate,
+            and_gate,
+            or_gate,
+            nand_gate,
+            gnd,
+            vcc,
+            buffer,
+            xor_gate,
+            nor_gate,
+            xnor_gate,
+            switch,
+            led,
+            clock,
+            dff,
+            dff_sr,
+            register,
+            ram,
+            ram_sync,
+            divider,
+            button,
+            even_parity,
+            odd_parity,
+            delay,
         ]
+        .into_iter()
+        // unwrap: the built in types are known to be valid
+        .map(|builder| Rc::new(builder.build().unwrap()))
+        .collect()
     }
 
-    pub fn anchor_offset(&self, orientation: Orientation) -> Vec2 {
+    pub fn anchor_offset(&self, orientation: Orientation, mirrored: bool) -> Vec2 {
         let a = self.anchor_offset;
-        match orientation {
+        let mut rotated = match orientation {
             Orientation::North => a,
             Orientation::East => Vec2::new(self.size.height - a.y, a.x),
             Orientation::South => Vec2::new(self.size.width - a.x, self.size.height - a.y),
             Orientation::West => Vec2::new(a.y, self.size.width - a.x),
+        };
+        if mirrored {
+            rotated.x = self.rotated_size(orientation).width - rotated.x;
+        }
+        rotated
+    }
+
+    /// Unit vector pointing out of the body from the edge a pin sits on, in the unrotated icon
+    fn pin_outward(&self, pin: &Pin) -> Vec2 {
+        let pos = self.anchor_offset + pin.pos.to_widget_space().to_vec2();
+        if pos.y <= 0.0 {
+            Vec2::new(0.0, -1.0)
+        } else if pos.y >= self.size.height {
+            Vec2::new(0.0, 1.0)
+        } else if pos.x <= 0.0 {
+            Vec2::new(-1.0, 0.0)
+        } else {
+            Vec2::new(1.0, 0.0)
         }
     }
 
-    pub fn bounding_rect(&self, coords: Coords, orientation: Orientation) -> Rect {
-        let top_left = coords.to_canvas_space() - self.anchor_offset(orientation);
-        let size = match orientation {
+    fn rotated_size(&self, orientation: Orientation) -> Size {
+        match orientation {
             Orientation::North | Orientation::South => self.size,
             Orientation::East | Orientation::West => Size::new(self.size.height, self.size.width),
+        }
+    }
+
+    pub fn allows(&self, orientation: Orientation) -> bool {
+        self.orientations.contains(&orientation)
+    }
+
+    /// `orientation` if it's allowed, otherwise the first one that is
+    pub fn allowed_orientation(&self, orientation: Orientation) -> Orientation {
+        if self.allows(orientation) {
+            orientation
+        } else {
+            self.orientations[0]
+        }
+    }
+
+    /// The next allowed orientation after `orientation` going one way or the other, skipping
+    /// the ones the type leaves out
+    pub fn rotated(&self, orientation: Orientation, clockwise: bool) -> Orientation {
+        let mut rotated = orientation;
+        loop {
+            rotated = if clockwise {
+                rotated.rotated_cw()
+            } else {
+                rotated.rotated_ccw()
+            };
+            if self.allows(rotated) || rotated == orientation {
+                return rotated;
+            }
+        }
+    }
+
+    pub fn pin_count(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.pins
+            .iter()
+            .filter(|pin| pin.ty == PinType::Input)
+            .count()
+    }
+
+    pub fn has_variable_inputs(&self) -> bool {
+        self.variable_inputs
+    }
+
+    /// Whether the type's own symbol has a bubble on its output, so it can't be given another
+    pub fn has_output_bubble(&self) -> bool {
+        self.output_bubble
+    }
+
+    /// Whether components of the type hold on to what they take in when clocked
+    pub fn is_clocked(&self) -> bool {
+        self.pins.iter().any(|pin| pin.clocked)
+    }
+
+    /// Whether components of the type hold words as wide as their outputs, and give them out on
+    /// them. These are registers, which hold one, and RAM
+    pub fn holds_words(&self) -> bool {
+        matches!(
+            self.function,
+            Some(Function::Register) | Some(Function::Ram)
+        )
+    }
+
+    /// How many bits a component of the type holds beyond its `value`. A register holds one per
+    /// output. RAM holds a word for each address, followed by the word it last read. A clock
+    /// divider holds how many times CLK has risen since Q last flipped, and a delay line the levels
+    /// A has had, enough for the longest delay
+    pub fn memory_size(&self) -> usize {
+        match self.function {
+            Some(Function::Register) => self.output_count(),
+            Some(Function::Ram) => ((1 << self.address_width()) + 1) * self.output_count(),
+            Some(Function::Divider) => DIVIDER_COUNT_BITS,
+            Some(Function::Delay) => MAX_DELAY,
+            _ => 0,
+        }
+    }
+
+    /// How many A inputs a RAM has. It has those, then a D input per output, then WE and CLK
+    pub fn address_width(&self) -> usize {
+        match self.function {
+            Some(Function::Ram) => self.input_count() - self.output_count() - 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether a RAM's outputs only change when it's clocked, rather than following the address
+    pub fn has_clocked_reads(&self) -> bool {
+        self.function == Some(Function::Ram) && self.pins[0].clocked
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.pins
+            .iter()
+            .filter(|pin| pin.ty == PinType::Output)
+            .count()
+    }
+
+    /// The same gate with a different number of inputs. The body gets wider so the inputs stay
+    /// two cells apart and centred on the anchor. `None` if the type's inputs can't be changed, or
+    /// there would be too few or too many
+    pub fn with_inputs(&self, inputs: usize) -> Option<ComponentType> {
+        if !self.variable_inputs || !(MIN_GATE_INPUTS..=MAX_GATE_INPUTS).contains(&inputs) {
+            return None;
+        }
+        // each input past the icon's widens the body by the two cells between inputs
+        let extra = (inputs - MIN_GATE_INPUTS) as f64 * 32.0;
+        let output = self.pins.last().unwrap().name;
+        Some(ComponentType {
+            name: self.name.clone(),
+            size: Size::new(self.icon_size.width + extra, self.icon_size.height),
+            anchor_offset: Vec2::new((self.icon_size.width + extra) / 2.0, self.anchor_offset.y),
+            icon: self.icon.clone(),
+            icon_size: self.icon_size,
+            output_bubble: self.output_bubble,
+            description: gate_description(self.function?, &INPUT_NAMES[..inputs]),
+            function: self.function,
+            pins: gate_pins(&INPUT_NAMES[..inputs], output),
+            orientations: self.orientations.clone(),
+            variable_inputs: true,
+        })
+    }
+
+    /// Where a pin of this gate ends up in the pin list once it's given a different number of
+    /// inputs, or `None` for an input that goes. Gates list their inputs first, so only the pins
+    /// after them move
+    pub fn resized_pin(&self, pin: usize, inputs: usize) -> Option<usize> {
+        let old = self.input_count();
+        if pin < old {
+            Some(pin).filter(|pin| *pin < inputs)
+        } else {
+            Some(pin - old + inputs)
+        }
+    }
+
+    /// Whether the other type has the same pins in the same places, so that swapping one for the
+    /// other keeps wires on the same pins
+    pub fn same_pin_layout(&self, other: &ComponentType) -> bool {
+        self.pins.len() == other.pins.len()
+            && self
+                .pins
+                .iter()
+                .zip(other.pins.iter())
+                .all(|(a, b)| a.pos == b.pos && a.ty == b.ty)
+    }
+
+    pub fn bounding_rect(&self, coords: Coords, orientation: Orientation, mirrored: bool) -> Rect {
+        let top_left = coords.to_canvas_space() - self.anchor_offset(orientation, mirrored);
+        Rect::from_origin_size(top_left, self.rotated_size(orientation))
+    }
+}
+
+/// Puts together a `ComponentType`, checking that the pieces fit when it's built
+pub struct ComponentTypeBuilder {
+    name: String,
+    size: Size,
+    anchor_offset: Vec2,
+    icon: Option<Result<SvgData, String>>,
+    output_bubble: bool,
+    description: String,
+    function: Option<Function>,
+    pins: Vec<Pin>,
+    orientations: Vec<Orientation>,
+    variable_inputs: bool,
+}
+
+impl ComponentTypeBuilder {
+    /// A type with no size, icon or pins yet, which can face any way
+    pub fn new(name: &str) -> Self {
+        ComponentTypeBuilder {
+            name: name.to_owned(),
+            size: Size::ZERO,
+            anchor_offset: Vec2::ZERO,
+            icon: None,
+            output_bubble: false,
+            description: String::new(),
+            function: None,
+            pins: Vec::new(),
+            orientations: Orientation::ALL.to_vec(),
+            variable_inputs: false,
+        }
+    }
+
+    /// The size of the icon when facing north
+    pub fn size(mut self, width: f64, height: f64) -> Self {
+        self.size = Size::new(width, height);
+        self
+    }
+
+    /// Where in the unrotated icon the component's coordinates are
+    pub fn anchor(mut self, x: f64, y: f64) -> Self {
+        self.anchor_offset = Vec2::new(x, y);
+        self
+    }
+
+    pub fn icon_from_str(mut self, svg: &str) -> Self {
+        self.icon = Some(SvgData::from_str(svg).map_err(|e| e.to_string()));
+        self
+    }
+
+    /// Marks the icon as drawing its own inversion bubble on the output
+    pub fn output_bubble(mut self) -> Self {
+        self.output_bubble = true;
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_owned();
+        self
+    }
+
+    pub fn function(mut self, function: Function) -> Self {
+        self.function = Some(function);
+        self
+    }
+
+    /// Adds an input at a position relative to the anchor, in grid cells
+    pub fn input_pin(mut self, name: &'static str, x: isize, y: isize) -> Self {
+        self.pins.push(Pin::new(name, x, y, PinType::Input));
+        self
+    }
+
+    /// Adds an input that's only taken in when the component is clocked, see `Pin::clocked`
+    pub fn clocked_input_pin(mut self, name: &'static str, x: isize, y: isize) -> Self {
+        let mut pin = Pin::new(name, x, y, PinType::Input);
+        pin.clocked = true;
+        self.pins.push(pin);
+        self
+    }
+
+    /// Adds an output at a position relative to the anchor, in grid cells
+    pub fn output_pin(mut self, name: &'static str, x: isize, y: isize) -> Self {
+        self.pins.push(Pin::new(name, x, y, PinType::Output));
+        self
+    }
+
+    /// Lays out pins the way gates have them: the inputs spread evenly along the bottom edge, two
+    /// cells apart and centred on the anchor, and the output two cells above it
+    pub fn gate_pins(mut self, inputs: &[&'static str], output: &'static str) -> Self {
+        self.pins.extend(gate_pins(inputs, output));
+        self
+    }
+
+    /// Lets components of the type have anywhere from two to eight inputs. The type must be a gate
+    /// with two inputs laid out by `gate_pins`
+    pub fn variable_inputs(mut self) -> Self {
+        self.variable_inputs = true;
+        self
+    }
+
+    /// Limits which ways components of the type can face. They're kept in clockwise order
+    // every built in type can face any way, so only tests limit them
+    #[cfg(test)]
+    pub fn orientations(mut self, orientations: &[Orientation]) -> Self {
+        self.orientations = Orientation::ALL
+            .iter()
+            .copied()
+            .filter(|o| orientations.contains(o))
+            .collect();
+        self
+    }
+
+    /// Checks that the icon parsed, the anchor is on the icon, and every pin is on or inside its
+    /// edges, with no two pins in the same place
+    pub fn build(self) -> Result<ComponentType, String> {
+        let name = self.name.clone();
+        let err = |msg: String| format!("component type `{}`: {}", name, msg);
+        let icon = self
+            .icon
+            .ok_or_else(|| err("missing icon".to_owned()))?
+            .map_err(|e| err(format!("bad icon: {}", e)))?;
+        let size = self.size;
+        if size.width <= 0.0 || size.height <= 0.0 {
+            return Err(err("size must not be empty".to_owned()));
+        }
+        let on_icon =
+            |p: Point| (0.0..=size.width).contains(&p.x) && (0.0..=size.height).contains(&p.y);
+        if !on_icon(self.anchor_offset.to_point()) {
+            return Err(err("anchor is outside the icon".to_owned()));
+        }
+        for (i, pin) in self.pins.iter().enumerate() {
+            if !on_icon(self.anchor_offset.to_point() + pin.pos.to_widget_space().to_vec2()) {
+                return Err(err(format!("pin {} is outside the icon", pin.name)));
+            }
+            if self.pins[..i].iter().any(|other| other.pos == pin.pos) {
+                return Err(err(format!("pin {} is on top of another pin", pin.name)));
+            }
+        }
+        if self.orientations.is_empty() {
+            return Err(err("it must be able to face at least one way".to_owned()));
+        }
+        if self.variable_inputs {
+            let inputs = self.pins.iter().take_while(|pin| pin.ty == PinType::Input);
+            let gate = self.function.is_some()
+                && inputs.count() == MIN_GATE_INPUTS
+                && self.pins.len() == MIN_GATE_INPUTS + 1;
+            if !gate {
+                return Err(err("only two input gates can have more inputs".to_owned()));
+            }
+        }
+        Ok(ComponentType {
+            name: self.name,
+            size: self.size,
+            anchor_offset: self.anchor_offset,
+            icon,
+            icon_size: self.size,
+            output_bubble: self.output_bubble,
+            description: self.description,
+            function: self.function,
+            pins: self.pins,
+            orientations: self.orientations,
+            variable_inputs: self.variable_inputs,
+        })
+    }
+}
+
+/// The pins gates have: the inputs spread evenly along the bottom edge, two cells apart and
+/// centred on the anchor, and the output two cells above it
+fn gate_pins(inputs: &[&'static str], output: &'static str) -> Vec<Pin> {
+    let n = inputs.len() as isize;
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Pin::new(name, 2 * i as isize - (n - 1), 1, PinType::Input))
+        .chain(std::iter::once(Pin::new(output, 0, -2, PinType::Output)))
+        .collect()
+}
+
+/// A register holding the given number of bits. The D inputs are along the bottom edge under the
+/// Q outputs they go to, two cells apart like gate inputs, with EN on the left and CLK on the right
+fn register(width: usize) -> ComponentTypeBuilder {
+    let size = 32.0 * width as f64;
+    let icon = format!(
+        r##"<svg width="{0}" height="48" xmlns="http://www.w3.org/2000/svg">
+  <rect x="1" y="1" width="{1}" height="46" rx="2" fill="none" stroke="#000000" stroke-width="2" />
+  <polyline points="{2},26 {3},32 {2},38" fill="none" stroke="#000000" stroke-width="2" />
+</svg>"##,
+        size,
+        size - 2.0,
+        size - 1.0,
+        size - 9.0
+    );
+    let description = format!(
+        "{} {} {} when CLK rises while EN is high",
+        pin_range("Q", width),
+        if width == 1 { "takes" } else { "take" },
+        pin_range("D", width)
+    );
+    let n = width as isize;
+    let x = |i: usize| 2 * i as isize - (n - 1);
+    let mut builder = ComponentTypeBuilder::new("register")
+        .size(size, 48.0)
+        .anchor(size / 2.0, 32.0)
+        .icon_from_str(&icon)
+        .description(&description)
+        .function(Function::Register);
+    for (i, name) in DATA_INPUT_NAMES[..width].iter().enumerate() {
+        builder = builder.clocked_input_pin(name, x(i), 1);
+    }
+    builder = builder
+        .clocked_input_pin("EN", -n, 0)
+        .clocked_input_pin("CLK", n, 0);
+    for (i, name) in DATA_OUTPUT_NAMES[..width].iter().enumerate() {
+        builder = builder.output_pin(name, x(i), -2);
+    }
+    builder
+}
+
+/// A RAM with the given number of address bits and bits in each word. The D inputs and Q outputs
+/// are laid out like a register's, with the A inputs down the left edge from A0 and WE and CLK
+/// down the right. It's tall enough for all of them, and at least as tall as a register
+fn ram(address_width: usize, width: usize, clocked_reads: bool) -> ComponentTypeBuilder {
+    let size = 32.0 * width as f64;
+    // rows of the grid the body spans, with the A inputs on every row between the top and bottom
+    let rows = address_width.max(2) + 1;
+    let height = 16.0 * rows as f64;
+    let icon = format!(
+        r##"<svg width="{0}" height="{4}" xmlns="http://www.w3.org/2000/svg">
+  <rect x="1" y="1" width="{1}" height="{5}" rx="2" fill="none" stroke="#000000" stroke-width="2" />
+  <polyline points="{2},26 {3},32 {2},38" fill="none" stroke="#000000" stroke-width="2" />
+</svg>"##,
+        size,
+        size - 2.0,
+        size - 1.0,
+        size - 9.0,
+        height,
+        height - 2.0
+    );
+    let (name, reads) = if clocked_reads {
+        ("ram-sync", " as of when CLK last rose")
+    } else {
+        ("ram", "")
+    };
+    let description = format!(
+        "{} {} the word at {}{}. {} {} written to it when CLK rises while WE is high",
+        pin_range("Q", width),
+        if width == 1 { "gives" } else { "give" },
+        pin_range("A", address_width),
+        reads,
+        pin_range("D", width),
+        if width == 1 { "is" } else { "are" }
+    );
+    let n = width as isize;
+    let x = |i: usize| 2 * i as isize - (n - 1);
+    let top = 1 - rows as isize;
+    let mut builder = ComponentTypeBuilder::new(name)
+        .size(size, height)
+        .anchor(size / 2.0, height - 16.0)
+        .icon_from_str(&icon)
+        .description(&description)
+        .function(Function::Ram);
+    for (i, name) in ADDRESS_NAMES[..address_width].iter().enumerate() {
+        let y = top + 1 + i as isize;
+        builder = if clocked_reads {
+            builder.clocked_input_pin(name, -n, y)
+        } else {
+            builder.input_pin(name, -n, y)
         };
-        Rect::from_origin_size(top_left, size)
+    }
+    for (i, name) in DATA_INPUT_NAMES[..width].iter().enumerate() {
+        builder = builder.clocked_input_pin(name, x(i), 1);
+    }
+    builder = builder
+        .clocked_input_pin("WE", n, top + 1)
+        .clocked_input_pin("CLK", n, top + 2);
+    for (i, name) in DATA_OUTPUT_NAMES[..width].iter().enumerate() {
+        builder = builder.output_pin(name, x(i), top);
+    }
+    builder
+}
+
+/// Names a run of pins numbered from zero, like `D0–D3`, or just `D0` if there's one
+fn pin_range(prefix: &str, count: usize) -> String {
+    if count == 1 {
+        format!("{}0", prefix)
+    } else {
+        format!("{0}0–{0}{1}", prefix, count - 1)
+    }
+}
+
+/// Describes a gate with the given inputs, like `Y = ¬(A · B · C)`
+fn gate_description(function: Function, inputs: &[&str]) -> String {
+    let parity = match function {
+        Function::EvenParity => Some("even"),
+        Function::OddParity => Some("odd"),
+        _ => None,
+    };
+    if let Some(parity) = parity {
+        return format!(
+            "Y makes an {} number of {} and Y high",
+            parity,
+            inputs.join(", ")
+        );
+    }
+    let (operator, inverted) = match function {
+        Function::And => (" · ", false),
+        Function::Or => (" + ", false),
+        Function::Nand => (" · ", true),
+        Function::Nor => (" + ", true),
+        Function::Xor => (" ⊕ ", false),
+        Function::Xnor => (" ⊕ ", true),
+        _ => unreachable!("only two input gates are described this way"),
+    };
+    let inputs = inputs.join(operator);
+    if inverted {
+        format!("Y = ¬({})", inputs)
+    } else {
+        format!("Y = {}", inputs)
     }
 }
 
 #[derive(Clone, Data)]
 pub struct ComponentInstance {
-    coords: Coords,
+    pub coords: Coords,
     ty: Rc<ComponentType>,
-    orientation: Orientation,
+    pub orientation: Orientation,
+    /// Mirrored components are flipped left to right after being rotated, so e.g. an east-facing
+    /// gate has its output on the left. Pins keep their place in the type's pin list, so their
+    /// roles don't change
+    pub mirrored: bool,
+    /// Draws an inversion bubble on every output, meaning the outputs are negated
+    pub output_inverted: bool,
+    /// Which pins have an inversion bubble of their own, by index in the type's pin list. Only
+    /// inputs are inverted this way
+    pub inverted_inputs: im::Vector<bool>,
+    /// Which pins are marked as deliberately left unconnected, by index in the type's pin list
+    pub no_connect: im::Vector<bool>,
+    /// Disabled components are drawn ghosted
+    pub enabled: bool,
+    /// What a switch is switched to, the level a clock has got to, or what a flip-flop holds. LEDs
+    /// are painted lit when this is set, which the canvas does from the level on their input
+    pub value: bool,
+    /// The level on a clocked component's CLK input when the circuit last settled, so that it
+    /// going high can be told apart from it staying high
+    pub clock_input: bool,
+    /// What a register or RAM holds, each word a bit per output in pin list order, or the count a
+    /// clock divider has got to, lowest bit first, or the levels a delay line's A has had, newest
+    /// first. See `ComponentType::memory_size`. Empty for
+    /// other types
+    pub memory: im::Vector<bool>,
+    /// How many times CLK rises before a clock divider flips Q
+    pub divisor: usize,
+    /// How many passes of settling a delay line's Y lags behind A
+    pub delay: usize,
+    /// How long a clock stays at each level before flipping
+    #[data(same_fn = "PartialEq::eq")]
+    pub period: Duration,
+    /// Paused clocks stay at whatever level they've got to
+    pub paused: bool,
 }
 
 impl ComponentInstance {
     pub fn new(coords: Coords, ty: Rc<ComponentType>, orientation: Orientation) -> Self {
+        let inverted_inputs = im::Vector::from(vec![false; ty.pins.len()]);
+        let memory = im::Vector::from(vec![false; ty.memory_size()]);
         ComponentInstance {
             coords,
             ty,
             orientation,
+            mirrored: false,
+            output_inverted: false,
+            no_connect: inverted_inputs.clone(),
+            inverted_inputs,
+            enabled: true,
+            value: false,
+            clock_input: false,
+            memory,
+            period: DEFAULT_CLOCK_PERIOD,
+            paused: false,
+            divisor: DEFAULT_DIVISOR,
+            delay: DEFAULT_DELAY,
+        }
+    }
+
+    pub fn ty(&self) -> &ComponentType {
+        &self.ty
+    }
+
+    /// Whether it's a clock that's running
+    pub fn ticks(&self) -> bool {
+        self.ty.function == Some(Function::Clock) && !self.paused
+    }
+
+    /// Takes in the levels on a clocked component's inputs, in pin list order and after their
+    /// bubbles. A flip-flop takes D if CLK has gone high since last time, and S and R override
+    /// that. Returns whether anything changed, including the CLK level it remembers
+    pub fn latch(&mut self, inputs: &[bool]) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        match self.ty.function {
+            Some(Function::FlipFlop) => {
+                let (d, clock) = (inputs[0], inputs[1]);
+                let held = if clock && !self.clock_input {
+                    d
+                } else {
+                    self.value
+                };
+                let value = Function::FlipFlop.evaluate(inputs, held);
+                let changed = value != self.value || clock != self.clock_input;
+                self.value = value;
+                self.clock_input = clock;
+                changed
+            },
+            Some(Function::Register) => {
+                let width = self.memory.len();
+                let (enable, clock) = (inputs[width], inputs[width + 1]);
+                let rising = clock && !self.clock_input;
+                let mut changed = clock != self.clock_input;
+                self.clock_input = clock;
+                let word = im::Vector::from(&inputs[..width]);
+                // only replaced when it's different, so an unchanged register stays the same
+                if rising && enable && word != self.memory {
+                    self.memory = word;
+                    changed = true;
+                }
+                changed
+            },
+            Some(Function::Ram) => {
+                let (address_width, width) = (self.ty.address_width(), self.ty.output_count());
+                let (write, clock) = (
+                    inputs[address_width + width],
+                    inputs[address_width + width + 1],
+                );
+                let rising = clock && !self.clock_input;
+                let mut changed = clock != self.clock_input;
+                self.clock_input = clock;
+                if rising {
+                    let start = self.address(inputs) * width;
+                    let last_read = (1 << address_width) * width;
+                    let mut memory = self.memory.clone();
+                    // what's read is the word from before it's written over
+                    for bit in 0..width {
+                        memory.set(last_read + bit, self.memory[start + bit]);
+                        if write {
+                            memory.set(start + bit, inputs[address_width + bit]);
+                        }
+                    }
+                    if memory != self.memory {
+                        self.memory = memory;
+                        changed = true;
+                    }
+                }
+                changed
+            },
+            Some(Function::Divider) => {
+                let clock = inputs[0];
+                let rising = clock && !self.clock_input;
+                let changed = clock != self.clock_input;
+                self.clock_input = clock;
+                if rising {
+                    let count = self.count() + 1;
+                    let count = if count >= self.divisor {
+                        self.value = !self.value;
+                        0
+                    } else {
+                        count
+                    };
+                    self.memory = (0..DIVIDER_COUNT_BITS)
+                        .map(|bit| count >> bit & 1 == 1)
+                        .collect();
+                }
+                changed
+            },
+            // every pass shifts A along the line, so it stops changing once it's full of one
+            // level
+            Some(Function::Delay) => {
+                // only as far along as the delay, so it doesn't take longer to fill than that
+                let mut memory = self.memory.clone();
+                memory.insert(0, inputs[0]);
+                memory.remove(self.delay);
+                if memory == self.memory {
+                    return false;
+                }
+                self.value = memory[self.delay - 1];
+                self.memory = memory;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// How many times a clock divider's CLK has risen since Q last flipped
+    fn count(&self) -> usize {
+        self.memory
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| **bit)
+            .map(|(i, _)| 1 << i)
+            .sum()
+    }
+
+    /// Makes a clock divider flip Q after CLK rises one more or one fewer time, starting the count
+    /// again. Returns false if it isn't a clock divider, or can't count any more or fewer
+    pub fn change_divisor(&mut self, more: bool) -> bool {
+        let divisor = if more {
+            self.divisor + 1
+        } else {
+            self.divisor - 1
+        };
+        if self.ty.function != Some(Function::Divider) || !(1..=MAX_DIVISOR).contains(&divisor) {
+            return false;
+        }
+        self.divisor = divisor;
+        self.memory = im::Vector::from(vec![false; DIVIDER_COUNT_BITS]);
+        true
+    }
+
+    /// Makes a delay line lag one more or one less pass of settling, starting it off empty again.
+    /// Returns false if it isn't a delay line, or can't lag any more or less
+    pub fn change_delay(&mut self, more: bool) -> bool {
+        let delay = if more { self.delay + 1 } else { self.delay - 1 };
+        if self.ty.function != Some(Function::Delay) || !(1..=MAX_DELAY).contains(&delay) {
+            return false;
+        }
+        self.delay = delay;
+        self.value = false;
+        self.memory = im::Vector::from(vec![false; MAX_DELAY]);
+        true
+    }
+
+    /// The address on a RAM's A inputs, given the levels on its inputs, with A0 as the lowest bit
+    fn address(&self, inputs: &[bool]) -> usize {
+        inputs[..self.ty.address_width()]
+            .iter()
+            .enumerate()
+            .filter(|(_, bit)| **bit)
+            .map(|(i, _)| 1 << i)
+            .sum()
+    }
+
+    /// What a register or RAM gives on each of its outputs, given the levels on its inputs, before
+    /// any inversion bubbles. `None` for other types, whose outputs all give the same
+    pub fn word_outputs(&self, inputs: &[bool]) -> Option<Vec<bool>> {
+        let width = self.ty.output_count();
+        let start = match self.ty.function {
+            Some(Function::Register) => 0,
+            Some(Function::Ram) if self.ty.has_clocked_reads() => {
+                (1 << self.ty.address_width()) * width
+            },
+            Some(Function::Ram) => self.address(inputs) * width,
+            _ => return None,
+        };
+        Some(
+            self.memory
+                .iter()
+                .skip(start)
+                .take(width)
+                .copied()
+                .collect(),
+        )
+    }
+
+    /// The words a RAM holds, in address order, with D0 as the lowest bit of each
+    pub fn words(&self) -> Vec<usize> {
+        let width = self.ty.output_count();
+        (0..1 << self.ty.address_width())
+            .map(|address| {
+                (0..width)
+                    .filter(|bit| self.memory[address * width + bit])
+                    .map(|bit| 1 << bit)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Whether the input with the given index in the type's pin list is only taken in when the
+    /// component is clocked
+    pub fn is_clocked_input(&self, pin: usize) -> bool {
+        self.ty.pins[pin].clocked
+    }
+
+    /// Swaps the type, keeping the position, orientation and flags. Types with a bubble in their
+    /// icon can't be inverted, so that flag is dropped for them, and inverted inputs and no-connect
+    /// marks are only kept if the pins are the same. A gate with extra inputs keeps them when it
+    /// becomes another kind of gate. Returns whether the pins are the same, so that wires stay on
+    /// them
+    pub fn set_ty(&mut self, ty: Rc<ComponentType>) -> bool {
+        let inputs = self.ty.input_count();
+        let ty = match ty.with_inputs(inputs) {
+            Some(resized) if self.ty.variable_inputs && ty.input_count() != inputs => {
+                Rc::new(resized)
+            },
+            _ => ty,
+        };
+        if ty.output_bubble {
+            self.output_inverted = false;
+        }
+        let same_pins = self.ty.same_pin_layout(&ty);
+        if !same_pins {
+            self.inverted_inputs = im::Vector::from(vec![false; ty.pins.len()]);
+            self.no_connect = self.inverted_inputs.clone();
+        }
+        if ty.memory_size() != self.memory.len() {
+            self.memory = im::Vector::from(vec![false; ty.memory_size()]);
+        }
+        self.orientation = ty.allowed_orientation(self.orientation);
+        self.ty = ty;
+        same_pins
+    }
+
+    /// Gives a register or RAM words with a different number of bits, keeping the bits it holds
+    /// that are still there, and the flags on pins whose names are still there. Returns false if
+    /// it can't have that many, or already does
+    pub fn set_width(&mut self, width: usize) -> bool {
+        if !(1..=MAX_WORD_WIDTH).contains(&width) || width == self.ty.output_count() {
+            return false;
+        }
+        let ty = match self.ty.function {
+            Some(Function::Register) => register(width),
+            Some(Function::Ram) => ram(self.ty.address_width(), width, self.ty.has_clocked_reads()),
+            _ => return false,
+        };
+        // unwrap: registers and RAM of any size up to the most are valid
+        self.reshape(ty.build().unwrap());
+        true
+    }
+
+    /// Gives a RAM a different number of address bits, keeping the words at the addresses that
+    /// are still there. Returns false if it can't have that many, or already does
+    pub fn set_address_width(&mut self, address_width: usize) -> bool {
+        let changes = self.ty.function == Some(Function::Ram)
+            && (1..=MAX_ADDRESS_WIDTH).contains(&address_width)
+            && address_width != self.ty.address_width();
+        if !changes {
+            return false;
+        }
+        let ty = ram(
+            address_width,
+            self.ty.output_count(),
+            self.ty.has_clocked_reads(),
+        );
+        // unwrap: RAM of any size up to the most is valid
+        self.reshape(ty.build().unwrap());
+        true
+    }
+
+    /// Changes a register or RAM to one of the same kind with a different size. Pins keep their
+    /// flags by name, and words keep their bits by address, with the last word, the one RAM last
+    /// read, staying the last
+    fn reshape(&mut self, ty: ComponentType) {
+        let old_ty = Rc::clone(&self.ty);
+        let resize = |flags: &im::Vector<bool>| {
+            let resized: Vec<_> = ty
+                .pins
+                .iter()
+                .map(|pin| {
+                    let old = old_ty.pins.iter().position(|old| old.name == pin.name);
+                    matches!(old, Some(old) if flags[old])
+                })
+                .collect();
+            im::Vector::from(resized)
+        };
+        self.inverted_inputs = resize(&self.inverted_inputs);
+        self.no_connect = resize(&self.no_connect);
+        let (old_width, width) = (old_ty.output_count(), ty.output_count());
+        let (old_words, words) = (self.memory.len() / old_width, ty.memory_size() / width);
+        let old_word = |word: usize| {
+            if word == words - 1 {
+                Some(old_words - 1)
+            } else {
+                Some(word).filter(|word| *word < old_words - 1)
+            }
+        };
+        let memory = (0..words)
+            .flat_map(|word| (0..width).map(move |bit| (word, bit)))
+            .map(|(word, bit)| match old_word(word) {
+                Some(old) if bit < old_width => self.memory[old * old_width + bit],
+                _ => false,
+            })
+            .collect();
+        self.memory = memory;
+        self.ty = Rc::new(ty);
+    }
+
+    /// Gives a gate one more input or one fewer, or a register or RAM one more bit in each word or
+    /// one fewer. Returns where each of the old pins is in the new pin list, or `None` for a pin
+    /// that went. Returns `None` instead if it can't be resized that way
+    pub fn resize(&mut self, more: bool) -> Option<Vec<Option<usize>>> {
+        let old_ty = Rc::clone(&self.ty);
+        if old_ty.variable_inputs {
+            let inputs = if more {
+                old_ty.input_count() + 1
+            } else {
+                old_ty.input_count() - 1
+            };
+            if !self.set_input_count(inputs) {
+                return None;
+            }
+            Some(
+                (0..old_ty.pin_count())
+                    .map(|pin| old_ty.resized_pin(pin, inputs))
+                    .collect(),
+            )
+        } else {
+            let width = if more {
+                old_ty.output_count() + 1
+            } else {
+                old_ty.output_count().checked_sub(1)?
+            };
+            if !self.set_width(width) {
+                return None;
+            }
+            Some(self.pins_named_as(&old_ty))
         }
     }
 
+    /// Gives a RAM one more address bit or one fewer. Returns where each of the old pins is in
+    /// the new pin list like `resize`
+    pub fn resize_address(&mut self, more: bool) -> Option<Vec<Option<usize>>> {
+        let old_ty = Rc::clone(&self.ty);
+        let address_width = if more {
+            old_ty.address_width() + 1
+        } else {
+            old_ty.address_width().checked_sub(1)?
+        };
+        if !self.set_address_width(address_width) {
+            return None;
+        }
+        Some(self.pins_named_as(&old_ty))
+    }
+
+    /// Where each pin of another type is in this one's pin list, going by name
+    fn pins_named_as(&self, ty: &ComponentType) -> Vec<Option<usize>> {
+        ty.pins.iter().map(|pin| self.pin_named(pin.name)).collect()
+    }
+
+    /// Gives a gate a different number of inputs, keeping the flags on the pins that are still
+    /// there. Returns false if it can't have that many, or already does
+    pub fn set_input_count(&mut self, inputs: usize) -> bool {
+        let ty = match self.ty.with_inputs(inputs) {
+            Some(ty) if inputs != self.ty.input_count() => ty,
+            _ => return false,
+        };
+        let old_ty = Rc::clone(&self.ty);
+        let resize = |flags: &im::Vector<bool>| {
+            let mut resized = vec![false; ty.pins.len()];
+            for (pin, flag) in flags.iter().enumerate() {
+                if let Some(pin) = old_ty.resized_pin(pin, inputs) {
+                    resized[pin] = *flag;
+                }
+            }
+            im::Vector::from(resized)
+        };
+        self.inverted_inputs = resize(&self.inverted_inputs);
+        self.no_connect = resize(&self.no_connect);
+        self.ty = Rc::new(ty);
+        true
+    }
+
     pub fn bounding_rect(&self) -> Rect {
-        self.ty.bounding_rect(self.coords, self.orientation)
+        self.ty
+            .bounding_rect(self.coords, self.orientation, self.mirrored)
     }
 
-    pub fn paint(&self, ctx: &mut PaintCtx) {
+    /// Whether a point in canvas space is on the body, following the orientation. Pins reach a
+    /// little outside this, see `pin_at_canvas_point`
+    pub fn contains_point(&self, pos: Point) -> bool {
+        self.bounding_rect().contains(pos)
+    }
+
+    pub fn paint(&self, ctx: &mut PaintCtx, env: &Env) {
+        let alpha = if self.enabled { 1.0 } else { DISABLED_ALPHA };
         ctx.with_save(|ctx| {
-            ctx.transform(self.rotate_about_anchor());
-            self.ty.icon.to_piet(IDENTITY, ctx);
+            ctx.transform(self.icon_transform());
+            self.paint_value(ctx, env);
+            let icon_offset = (self.ty.size.width - self.ty.icon_size.width) / 2.0;
+            self.ty
+                .icon
+                .to_piet(Affine::translate((icon_offset, 0.0)), ctx);
+            if icon_offset > 0.0 {
+                // the input edge is drawn out to the extra inputs on either side
+                let y = self.ty.size.height - 1.0;
+                let edge = Line::new((0.0, y), (self.ty.size.width, y));
+                ctx.stroke(edge, &Color::BLACK, 2.0);
+            }
+            let inverted = self
+                .ty
+                .pins
+                .iter()
+                .enumerate()
+                .filter(|(i, pin)| match pin.ty {
+                    PinType::Output => self.output_inverted,
+                    PinType::Input => self.inverted_inputs[*i],
+                });
+            for (_, pin) in inverted {
+                // sits just inside the pin where the not and nand icons draw theirs
+                let center = self.anchor_offset().to_point() + pin.pos.to_widget_space().to_vec2()
+                    - self.ty.pin_outward(pin) * 5.0;
+                let bubble = Circle::new(center, 4.0);
+                ctx.fill(bubble, &env.get(theme::WINDOW_BACKGROUND_COLOR));
+                ctx.stroke(bubble, &Color::BLACK, 2.0);
+            }
+            // short leads out of the body, like schematic symbols have
+            let stub_length = env.get(PIN_STUB_LENGTH);
+            for pin in self.ty.pins.iter() {
+                let pos = self.anchor_offset().to_point() + pin.pos.to_widget_space().to_vec2();
+                let outer = pos + self.ty.pin_outward(pin) * stub_length;
+                ctx.stroke(Line::new(pos, outer), &Color::BLACK.with_alpha(alpha), 2.0);
+            }
+            if env.get(SHOW_PIN_DIRECTIONS) {
+                let mut arrows = BezPath::new();
+                for pin in self.ty.pins.iter() {
+                    let pos = self.anchor_offset().to_point() + pin.pos.to_widget_space().to_vec2();
+                    let outward = self.ty.pin_outward(pin);
+                    let outer = pos + outward * stub_length;
+                    let (tip, base) = match pin.ty {
+                        PinType::Input => (pos, outer),
+                        PinType::Output => (outer, pos),
+                    };
+                    let across = Vec2::new(-outward.y, outward.x) * 3.0;
+                    arrows.move_to(tip);
+                    arrows.line_to(base + across);
+                    arrows.line_to(base - across);
+                    arrows.close_path();
+                }
+                ctx.fill(arrows, &Color::BLACK.with_alpha(alpha));
+            }
+            if !self.enabled {
+                // svgs can't be drawn translucent, so wash the icon out with the background
+                // instead
+                let wash = env
+                    .get(theme::WINDOW_BACKGROUND_COLOR)
+                    .with_alpha(1.0 - alpha);
+                ctx.fill(self.ty.size.to_rect(), &wash);
+            }
+        });
+    }
 
-            ctx.transform(Affine::translate(self.anchor_offset()));
+    /// Paints the pins. This is separate from the body so that the canvas can put pins above every
+    /// component and wire
+    pub fn paint_pins(&self, ctx: &mut PaintCtx) {
+        let alpha = if self.enabled { 1.0 } else { DISABLED_ALPHA };
+        ctx.with_save(|ctx| {
+            ctx.transform(self.icon_transform() * Affine::translate(self.anchor_offset()));
+            // one fill for all of them rather than one per pin
+            let mut pins = BezPath::new();
             for pin in self.ty.pins.iter() {
-                ctx.fill(
-                    Rect::from_center_size(pin.pos.to_widget_space(), Size::new(2.0, 2.0)),
-                    &Color::GREEN,
-                );
+                let rect = Rect::from_center_size(pin.pos.to_widget_space(), Size::new(2.0, 2.0));
+                pins.extend(rect.path_elements(0.0));
+            }
+            ctx.fill(pins, &Color::GREEN.with_alpha(alpha));
+            let mut crosses = BezPath::new();
+            for (pin, _) in self
+                .ty
+                .pins
+                .iter()
+                .zip(self.no_connect.iter())
+                .filter(|(_, nc)| **nc)
+            {
+                let center = pin.pos.to_widget_space();
+                crosses.move_to(center + Vec2::new(-4.0, -4.0));
+                crosses.line_to(center + Vec2::new(4.0, 4.0));
+                crosses.move_to(center + Vec2::new(4.0, -4.0));
+                crosses.line_to(center + Vec2::new(-4.0, 4.0));
             }
+            ctx.stroke(crosses, &Color::BLACK.with_alpha(alpha), 1.5);
         });
     }
 
+    /// Fills in the inside of switch, LED, clock and flip-flop icons to show their value, and
+    /// shows each bit a register holds under its output
+    fn paint_value(&self, ctx: &mut PaintCtx, env: &Env) {
+        let color = if self.value {
+            env.get(HIGH_LEVEL_COLOR)
+        } else {
+            env.get(LOW_LEVEL_COLOR)
+        };
+        match self.ty.function {
+            Some(Function::Switch) => {
+                ctx.fill(RoundedRect::new(4.0, 8.0, 20.0, 20.0, 1.0), &color);
+                // the lever sits on the right when switched on
+                let x = if self.value { 13.0 } else { 5.0 };
+                let lever = RoundedRect::new(x, 9.0, x + 6.0, 19.0, 1.0);
+                ctx.fill(lever, &env.get(theme::WINDOW_BACKGROUND_COLOR));
+            },
+            Some(Function::Button) => ctx.fill(Circle::new((12.0, 14.0), 3.0), &color),
+            Some(Function::Light) => ctx.fill(Circle::new((12.0, 10.0), 7.0), &color),
+            // washed out while paused
+            Some(Function::Clock) => {
+                let alpha = if self.paused { DISABLED_ALPHA } else { 1.0 };
+                let track = RoundedRect::new(4.0, 8.0, 20.0, 20.0, 1.0);
+                ctx.fill(track, &color.with_alpha(alpha));
+            },
+            Some(Function::FlipFlop) => {
+                ctx.fill(RoundedRect::new(24.0, 12.0, 40.0, 28.0, 1.0), &color)
+            },
+            // how many times CLK rises for each flip, in the level on Q
+            Some(Function::Divider) => {
+                let layout = ctx
+                    .text()
+                    .new_text_layout(format!("÷{}", self.divisor))
+                    .font(FontFamily::SYSTEM_UI, 11.0)
+                    .text_color(color)
+                    .build()
+                    .unwrap();
+                let origin = Point::new(16.0 - layout.size().width / 2.0, 10.0);
+                ctx.draw_text(&layout, origin);
+            },
+            // how many steps Y lags, in the level on it
+            Some(Function::Delay) => {
+                let layout = ctx
+                    .text()
+                    .new_text_layout(format!("Δ{}", self.delay))
+                    .font(FontFamily::SYSTEM_UI, 11.0)
+                    .text_color(color)
+                    .build()
+                    .unwrap();
+                let origin = Point::new(16.0 - layout.size().width / 2.0, 10.0);
+                ctx.draw_text(&layout, origin);
+            },
+            Some(Function::Register) => {
+                for (i, bit) in self.memory.iter().enumerate() {
+                    let color = if *bit {
+                        env.get(HIGH_LEVEL_COLOR)
+                    } else {
+                        env.get(LOW_LEVEL_COLOR)
+                    };
+                    let x = 32.0 * i as f64;
+                    ctx.fill(RoundedRect::new(x + 8.0, 12.0, x + 24.0, 28.0, 1.0), &color);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Grid coordinates of each pin, in the same order as the type's pin list
+    pub fn resolved_pins(&self) -> impl Iterator<Item = Coords> + '_ {
+        self.ty.pins.iter().map(move |pin| {
+            let Coords { x, y } = pin.pos;
+            let (x, y) = match self.orientation {
+                Orientation::North => (x, y),
+                Orientation::East => (-y, x),
+                Orientation::South => (-x, -y),
+                Orientation::West => (y, -x),
+            };
+            let x = if self.mirrored { -x } else { x };
+            Coords::new(self.coords.x + x, self.coords.y + y)
+        })
+    }
+
+    /// The index of the pin under a point in canvas space. This is the same hit test the
+    /// component uses for clicks, so it follows the orientation and reaches a little outside the
+    /// body
+    pub fn pin_at_canvas_point(&self, pos: Point) -> Option<usize> {
+        self.pin_at(pos - self.bounding_rect().origin().to_vec2())
+    }
+
+    /// The name of the pin with the given index in the type's pin list
+    pub fn pin_name(&self, pin: usize) -> &'static str {
+        self.ty.pins[pin].name
+    }
+
+    /// The index in the type's pin list of the pin with the given name
+    pub fn pin_named(&self, name: &str) -> Option<usize> {
+        self.ty.pins.iter().position(|pin| pin.name == name)
+    }
+
+    /// The type of the pin with the given index in the type's pin list
+    pub fn pin_type(&self, pin: usize) -> PinType {
+        self.ty.pins[pin].ty
+    }
+
+    /// The index of the pin at the given grid coordinates, if there is one
+    pub fn pin_index_at(&self, coords: Coords) -> Option<usize> {
+        self.resolved_pins()
+            .position(|pin_coords| pin_coords == coords)
+    }
+
+    /// The type of the pin at the given grid coordinates, if there is one
+    pub fn pin_type_at(&self, coords: Coords) -> Option<PinType> {
+        self.pin_index_at(coords).map(|i| self.ty.pins[i].ty)
+    }
+
     fn anchor_offset(&self) -> Vec2 {
-        self.ty.anchor_offset(Orientation::North)
+        self.ty.anchor_offset(Orientation::North, false)
     }
 
     fn rotate_about_anchor(&self) -> Affine {
@@ -178,10 +1603,30 @@ impl ComponentInstance {
         recenter * Affine::rotate(self.orientation.angle())
     }
 
-    fn pin_bounding_rect(&self, i: usize) -> Rect {
+    /// Maps the unrotated, unmirrored icon into widget space
+    fn icon_transform(&self) -> Affine {
+        let mirror = if self.mirrored {
+            let width = self.ty.rotated_size(self.orientation).width;
+            Affine::new([-1.0, 0.0, 0.0, 1.0, width, 0.0])
+        } else {
+            IDENTITY
+        };
+        mirror * self.rotate_about_anchor()
+    }
+
+    /// The nearest pin to a point in widget space, if it's within `PIN_HIT_RADIUS`
+    fn pin_at(&self, pos: Point) -> Option<usize> {
+        (0..self.ty.pins.len())
+            .map(|i| (i, self.pin_center(i).distance(pos)))
+            .filter(|(_, distance)| *distance <= PIN_HIT_RADIUS)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Where a pin is in widget space, following the orientation
+    fn pin_center(&self, i: usize) -> Point {
         let pin = &self.ty.pins[i];
-        let point = self.rotate_about_anchor() * (pin.pos.to_widget_space() + self.anchor_offset());
-        Rect::from_center_size(point, Size::new(6.0, 6.0))
+        self.icon_transform() * (pin.pos.to_widget_space() + self.anchor_offset())
     }
 }
 
@@ -189,20 +1634,119 @@ impl ComponentInstance {
 pub struct ComponentState {
     pub instance: ComponentInstance,
     selected: bool,
-    dragging: Option<Vec2>,
+    /// Where the mouse was when the component started being dragged, and where the component was
+    dragging: Option<(Point, Coords)>,
 }
 
 impl ComponentState {
     pub fn new(coords: Coords, ty: Rc<ComponentType>, orientation: Orientation) -> Self {
+        ComponentState::from_instance(ComponentInstance::new(coords, ty, orientation))
+    }
+
+    pub fn from_instance(instance: ComponentInstance) -> Self {
         ComponentState {
-            instance: ComponentInstance::new(coords, ty, orientation),
+            instance,
             selected: false,
             dragging: None,
         }
     }
+
+    pub fn is_selected(&self) -> bool {
+        self.selected
+    }
+
+    /// Whether the component is following the mouse
+    pub fn is_dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    /// Adds the component to the selection
+    pub fn select(&mut self) {
+        self.selected = true;
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected = false;
+    }
+
+    /// Lets go of the component if it's being dragged or held down, for when a drag is cut short
+    pub fn end_drag(&mut self) {
+        self.dragging = None;
+        if self.instance.ty.function == Some(Function::Button) {
+            self.instance.value = false;
+        }
+    }
+
+    /// A selected copy of this component, ready to be dragged away from the original
+    pub fn duplicate(&self) -> Self {
+        ComponentState {
+            instance: self.instance.clone(),
+            selected: true,
+            dragging: None,
+        }
+    }
+}
+
+pub struct Component {
+    id: ItemId,
+    keymap: Rc<Keymap>,
+    /// Set while the pins are highlighted after a keyboard rotation, until the timer fires
+    rotation_preview: Option<TimerToken>,
+    /// The pin under the mouse, for actions that act on a single pin
+    hovered_pin: Option<usize>,
+    /// Where the component was when the mouse went down on it. If it's still there when the mouse
+    /// comes up, it was clicked rather than dragged
+    pressed_at: Option<Coords>,
+    /// Set while the icon turns to a new orientation: the angle it turns through on screen, and
+    /// how far into the animation it is. Only the painting is animated, the component is already
+    /// facing the new way
+    rotation_animation: Option<(f64, Duration)>,
+    /// The timer for a running clock's next flip. Timers can't be cancelled, so stopping the clock
+    /// forgets the token and the timer goes off without doing anything. A deleted clock's widget
+    /// goes with it, and so does the token
+    clock_timer: Option<TimerToken>,
 }
 
-pub struct Component(pub usize);
+impl Component {
+    pub fn new(id: ItemId, keymap: Rc<Keymap>) -> Self {
+        Component {
+            id,
+            keymap,
+            rotation_preview: None,
+            hovered_pin: None,
+            pressed_at: None,
+            rotation_animation: None,
+            clock_timer: None,
+        }
+    }
+
+    fn animate_rotation(
+        &mut self,
+        ctx: &mut druid::EventCtx,
+        instance: &ComponentInstance,
+        from: Orientation,
+        env: &Env,
+    ) {
+        if !env.get(ANIMATE_ROTATION) {
+            return;
+        }
+        let mut angle = instance.orientation.angle() - from.angle();
+        // always take the short way round
+        if angle > std::f64::consts::PI {
+            angle -= std::f64::consts::TAU;
+        } else if angle < -std::f64::consts::PI {
+            angle += std::f64::consts::TAU;
+        }
+        // mirroring happens after rotating, so it turns the other way on screen
+        if instance.mirrored {
+            angle = -angle;
+        }
+        self.rotation_animation = Some((angle, Duration::ZERO));
+        ctx.request_anim_frame();
+        // the icon's corners swing outside the usual paint insets while it turns
+        ctx.request_layout();
+    }
+}
 
 impl Widget<ComponentState> for Component {
     fn event(
@@ -210,14 +1754,27 @@ impl Widget<ComponentState> for Component {
         ctx: &mut druid::EventCtx,
         event: &druid::Event,
         data: &mut ComponentState,
-        _env: &druid::Env,
+        env: &druid::Env,
     ) {
+        if let Event::MouseDown(_) | Event::KeyDown(_) = event {
+            if self.rotation_preview.take().is_some() {
+                ctx.request_paint();
+            }
+        }
+
         match event {
             Event::MouseDown(ev) => {
-                if let Some(_pin) = (0..data.instance.ty.pins.len())
-                    .find(|i| data.instance.pin_bounding_rect(*i).contains(ev.pos))
-                {
-                    ctx.submit_command(BEGIN_WIRE_DRAW);
+                if let Some(pin) = data.instance.pin_at(ev.pos) {
+                    let pin_coords = data.instance.resolved_pins().nth(pin).unwrap();
+                    ctx.submit_command(BEGIN_WIRE_DRAW.with(pin_coords));
+                    // dragging from a pin only ever draws a wire, without touching the selection
+                    ctx.set_handled();
+                } else if ev.button == MouseButton::Middle {
+                    // off the pins the middle button is left for panning the canvas
+                } else if ev.mods.alt() {
+                    // the canvas makes the copy and starts dragging it, this one stays put
+                    ctx.submit_command(BEGIN_DUPLICATE_DRAG.with((self.id, ev.window_pos)));
+                    ctx.set_handled();
                 } else {
                     if !data.selected {
                         data.selected = true;
@@ -227,6 +1784,14 @@ impl Widget<ComponentState> for Component {
                         }
                     }
 
+                    if ev.button == MouseButton::Left {
+                        self.pressed_at = Some(data.instance.coords);
+                        // the drag that starts makes this active, so it hears the mouse being let
+                        // go even outside it
+                        if data.instance.ty.function == Some(Function::Button) {
+                            data.instance.value = true;
+                        }
+                    }
                     ctx.submit_command(BEGIN_DRAG.with(ev.window_pos));
                     ctx.request_focus();
                     ctx.set_handled();
@@ -235,27 +1800,129 @@ impl Widget<ComponentState> for Component {
             Event::MouseUp(_) => {
                 data.dragging = None;
                 ctx.set_active(false);
+                let clicked = self.pressed_at.take() == Some(data.instance.coords);
+                match data.instance.ty.function {
+                    Some(Function::Switch) if clicked => {
+                        data.instance.value = !data.instance.value;
+                        ctx.request_paint();
+                    },
+                    Some(Function::Clock) if clicked => {
+                        data.instance.paused = !data.instance.paused;
+                        ctx.request_paint();
+                    },
+                    Some(Function::Button) => data.instance.value = false,
+                    _ => {},
+                }
             },
             Event::MouseMove(ev) => {
-                if let Some(mouse_offset) = data.dragging {
-                    data.instance.coords = Coords::from_canvas_space(ev.window_pos - mouse_offset);
+                if let Some((mouse_start, start)) = data.dragging {
+                    data.instance.coords = start.dragged(mouse_start, ev.window_pos);
+                }
+                let hovered_pin = data.instance.pin_at(ev.pos);
+                if hovered_pin != self.hovered_pin {
+                    self.hovered_pin = hovered_pin;
+                    ctx.request_paint();
+                }
+                // only takes effect while hot, so leaving the component resets it
+                if self.hovered_pin.is_some() {
+                    ctx.set_cursor(&Cursor::Crosshair);
+                } else {
+                    ctx.set_cursor(&Cursor::OpenHand);
+                }
+            },
+            // ctrl+wheel is left alone so it can be used for zooming the canvas
+            Event::Wheel(ev) if data.selected && !ev.mods.ctrl() => {
+                // some platforms turn shift+wheel into horizontal scrolling
+                let delta = if ev.wheel_delta.y != 0.0 {
+                    ev.wheel_delta.y
+                } else {
+                    ev.wheel_delta.x
+                };
+                if delta != 0.0 {
+                    let orientation = data.instance.orientation;
+                    let clockwise = (delta > 0.0) != ev.mods.shift();
+                    data.instance.orientation = data.instance.ty.rotated(orientation, clockwise);
+                    self.animate_rotation(ctx, &data.instance, orientation, env);
+                    ctx.request_paint();
+                    ctx.set_handled();
                 }
             },
             Event::KeyDown(ev) => {
-                use druid::keyboard_types::Key;
                 let mut orientation = data.instance.orientation;
-                match ev.key {
-                    Key::Character(ref s) if s == "w" => orientation = Orientation::North,
-                    Key::Character(ref s) if s == "a" => orientation = Orientation::West,
-                    Key::Character(ref s) if s == "s" => orientation = Orientation::South,
-                    Key::Character(ref s) if s == "d" => orientation = Orientation::East,
+                let mut mirrored = data.instance.mirrored;
+                let mut output_inverted = data.instance.output_inverted;
+                match self.keymap.action(ev) {
+                    Some(Action::Face(o)) if data.instance.ty.allows(o) => orientation = o,
+                    Some(Action::RotateClockwise) => {
+                        orientation = data.instance.ty.rotated(orientation, true)
+                    },
+                    Some(Action::RotateCounterClockwise) => {
+                        orientation = data.instance.ty.rotated(orientation, false)
+                    },
+                    Some(Action::Mirror) => mirrored = !mirrored,
+                    Some(Action::ToggleEnabled) => {
+                        data.instance.enabled = !data.instance.enabled;
+                        ctx.request_paint();
+                    },
+                    Some(Action::InvertOutput) if !data.instance.ty.output_bubble => {
+                        output_inverted = !output_inverted
+                    },
+                    Some(Action::InvertInput) => {
+                        let input = self
+                            .hovered_pin
+                            .filter(|pin| data.instance.ty.pins[*pin].ty == PinType::Input);
+                        if let Some(pin) = input {
+                            let inverted = data.instance.inverted_inputs[pin];
+                            data.instance.inverted_inputs.set(pin, !inverted);
+                            ctx.request_paint();
+                        }
+                    },
+                    Some(Action::ToggleNoConnect) => {
+                        if let Some(pin) = self.hovered_pin {
+                            let marked = data.instance.no_connect[pin];
+                            data.instance.no_connect.set(pin, !marked);
+                            ctx.request_paint();
+                        }
+                    },
                     _ => {},
                 }
-                if orientation != data.instance.orientation {
+                if orientation != data.instance.orientation
+                    || mirrored != data.instance.mirrored
+                    || output_inverted != data.instance.output_inverted
+                {
+                    let old_orientation = data.instance.orientation;
                     data.instance.orientation = orientation;
+                    data.instance.mirrored = mirrored;
+                    data.instance.output_inverted = output_inverted;
+                    if orientation != old_orientation {
+                        self.rotation_preview = Some(ctx.request_timer(ROTATION_PREVIEW_DURATION));
+                        self.animate_rotation(ctx, &data.instance, old_orientation, env);
+                    }
+                    ctx.request_paint();
+                }
+            },
+            Event::AnimFrame(interval) => {
+                if let Some((angle, elapsed)) = self.rotation_animation {
+                    let elapsed = elapsed + Duration::from_nanos(*interval);
+                    if elapsed < ROTATION_ANIMATION_DURATION {
+                        self.rotation_animation = Some((angle, elapsed));
+                        ctx.request_anim_frame();
+                    } else {
+                        self.rotation_animation = None;
+                        ctx.request_layout();
+                    }
                     ctx.request_paint();
                 }
             },
+            Event::Timer(token) if self.rotation_preview == Some(*token) => {
+                self.rotation_preview = None;
+                ctx.request_paint();
+            },
+            Event::Timer(token) if self.clock_timer == Some(*token) => {
+                data.instance.value = !data.instance.value;
+                self.clock_timer = Some(ctx.request_timer(data.instance.period));
+                ctx.request_paint();
+            },
             Event::Command(c) if c.is(DESELECT_ALL) => {
                 let widget_id = c.get(DESELECT_ALL).unwrap();
                 if *widget_id != ctx.widget_id() {
@@ -267,11 +1934,9 @@ impl Widget<ComponentState> for Component {
             },
             Event::Command(c) if c.is(BEGIN_DRAG) && data.selected => {
                 let window_pos = c.get(BEGIN_DRAG).unwrap();
-                data.dragging = Some(
-                    *window_pos
-                        - data.instance.anchor_offset()
-                        - data.instance.bounding_rect().origin(),
-                );
+                // every selected component and wire gets this, so they all move together from
+                // wherever they each start
+                data.dragging = Some((*window_pos, data.instance.coords));
                 ctx.set_active(true);
             },
             _ => {},
@@ -280,20 +1945,43 @@ impl Widget<ComponentState> for Component {
 
     fn lifecycle(
         &mut self,
-        _ctx: &mut druid::LifeCycleCtx,
-        _event: &druid::LifeCycle,
-        _data: &ComponentState,
+        ctx: &mut druid::LifeCycleCtx,
+        event: &druid::LifeCycle,
+        data: &ComponentState,
         _env: &druid::Env,
     ) {
+        match event {
+            druid::LifeCycle::WidgetAdded if data.instance.ticks() => {
+                self.clock_timer = Some(ctx.request_timer(data.instance.period));
+            },
+            // the selection outline depends on focus
+            druid::LifeCycle::FocusChanged(_) => ctx.request_paint(),
+            druid::LifeCycle::HotChanged(false) if self.hovered_pin.is_some() => {
+                self.hovered_pin = None;
+                ctx.request_paint();
+            },
+            _ => {},
+        }
     }
 
     fn update(
         &mut self,
-        _ctx: &mut druid::UpdateCtx,
-        _old_data: &ComponentState,
-        _data: &ComponentState,
+        ctx: &mut druid::UpdateCtx,
+        old_data: &ComponentState,
+        data: &ComponentState,
         _env: &druid::Env,
     ) {
+        // pins are kept by index, so the hovered one may not be there any more
+        if !Rc::ptr_eq(&old_data.instance.ty, &data.instance.ty) && self.hovered_pin.is_some() {
+            self.hovered_pin = None;
+            ctx.request_paint();
+        }
+        // a clock starts timing afresh when it's unpaused or its period changes
+        if !data.instance.ticks() {
+            self.clock_timer = None;
+        } else if !old_data.instance.ticks() || old_data.instance.period != data.instance.period {
+            self.clock_timer = Some(ctx.request_timer(data.instance.period));
+        }
     }
 
     fn layout(
@@ -303,13 +1991,56 @@ impl Widget<ComponentState> for Component {
         data: &ComponentState,
         _env: &druid::Env,
     ) -> Size {
-        ctx.set_paint_insets(Insets::uniform(8.0));
-        bc.constrain(data.instance.ty.size)
+        let size = data.instance.bounding_rect().size();
+        let insets = if self.rotation_animation.is_some() {
+            // enough for the corners to swing through
+            let radius = size.width.hypot(size.height) / 2.0;
+            radius - size.width.min(size.height) / 2.0 + 8.0
+        } else {
+            8.0
+        };
+        ctx.set_paint_insets(Insets::uniform(insets));
+        bc.constrain(size)
     }
 
-    fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &ComponentState, _env: &druid::Env) {
-        data.instance.paint(ctx);
+    fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &ComponentState, env: &druid::Env) {
+        if let Some((angle, elapsed)) = self.rotation_animation {
+            let t = elapsed.as_secs_f64() / ROTATION_ANIMATION_DURATION.as_secs_f64();
+            // eases out, so it settles gently into the new orientation
+            let remaining = angle * (1.0 - t).powi(3);
+            let center = ctx.size().to_vec2() / 2.0;
+            ctx.with_save(|ctx| {
+                ctx.transform(
+                    Affine::translate(center)
+                        * Affine::rotate(-remaining)
+                        * Affine::translate(-center),
+                );
+                data.instance.paint(ctx, env);
+            });
+        } else {
+            data.instance.paint(ctx, env);
+        }
+        if env.get(SHOW_PINS) {
+            let instance = data.instance.clone();
+            ctx.paint_with_z_index(PIN_LAYER, move |ctx| instance.paint_pins(ctx));
+        }
+
+        // a bigger, brighter dot on the pin a click would start a wire from
+        if let Some(pin) = self.hovered_pin {
+            let center = data.instance.pin_center(pin);
+            let color = env.get(HOVERED_PIN_COLOR);
+            ctx.paint_with_z_index(PIN_LAYER, move |ctx| {
+                ctx.fill(Rect::from_center_size(center, Size::new(5.0, 5.0)), &color);
+            });
+        }
+
         if data.selected {
+            // the focused component is the one keyboard commands go to
+            let color = if ctx.has_focus() {
+                env.get(SELECTION_COLOR)
+            } else {
+                env.get(SECONDARY_SELECTION_COLOR)
+            };
             // we're painting in widget space already so the bounding rect needs to be translated
             // back
             let selection_rect = data
@@ -317,11 +2048,310 @@ impl Widget<ComponentState> for Component {
                 .bounding_rect()
                 .with_origin(Point::ORIGIN)
                 .inflate(4.0, 4.0);
-            ctx.stroke(
-                RoundedRect::from_rect(selection_rect, 4.0),
-                &Color::AQUA,
-                1.0,
-            );
+            ctx.paint_with_z_index(SELECTION_LAYER, move |ctx| {
+                ctx.stroke(RoundedRect::from_rect(selection_rect, 4.0), &color, 1.0);
+            });
+        }
+
+        if ctx.has_focus() {
+            // a light ring on a dark halo, so it shows up on light and dark backgrounds alike
+            let color = env.get(FOCUS_RING_COLOR);
+            let focus_rect = data
+                .instance
+                .bounding_rect()
+                .with_origin(Point::ORIGIN)
+                .inflate(6.0, 6.0);
+            ctx.paint_with_z_index(SELECTION_LAYER, move |ctx| {
+                let ring = RoundedRect::from_rect(focus_rect, 6.0);
+                ctx.stroke(ring, &Color::BLACK, 3.0);
+                ctx.stroke(ring, &color, 1.0);
+            });
+        }
+
+        if self.rotation_preview.is_some() {
+            let color = env.get(SELECTION_COLOR);
+            let centers: Vec<_> = (0..data.instance.ty.pins.len())
+                .map(|i| data.instance.pin_center(i))
+                .collect();
+            ctx.paint_with_z_index(SELECTION_LAYER, move |ctx| {
+                for center in centers {
+                    ctx.stroke(Circle::new(center, 5.0), &color, 1.0);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist::Netlist;
+
+    #[test]
+    fn rotating_goes_round_the_compass() {
+        use Orientation::*;
+        for pair in [North, East, South, West, North].windows(2) {
+            assert_eq!(pair[0].rotated_cw(), pair[1]);
+            assert_eq!(pair[1].rotated_ccw(), pair[0]);
+        }
+        for orientation in Orientation::ALL {
+            assert_eq!(orientation.rotated_cw().rotated_ccw(), orientation);
+        }
+    }
+
+    #[test]
+    fn orientation_names_round_trip() {
+        for i in 0..Orientation::ALL.len() as u8 {
+            let orientation = Orientation::from_index(i);
+            assert_eq!(orientation, Orientation::ALL[i as usize]);
+            assert_eq!(orientation.to_string().parse(), Ok(orientation));
+        }
+        // indices wrap around
+        assert_eq!(Orientation::from_index(5), Orientation::East);
+        assert!("northeast".parse::<Orientation>().is_err());
+    }
+
+    #[test]
+    fn built_in_types() {
+        let types = ComponentType::enumerate();
+        let names: Vec<_> = types.iter().map(|ty| ty.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "not",
+                "and",
+                "or",
+                "nand",
+                "gnd",
+                "vcc",
+                "buffer",
+                "xor",
+                "nor",
+                "xnor",
+                "switch",
+                "led",
+                "clock",
+                "dff",
+                "dff-sr",
+                "register",
+                "ram",
+                "ram-sync",
+                "clock-divider",
+                "push-button",
+                "parity-even",
+                "parity-odd",
+                "delay"
+            ]
+        );
+        for ty in types.iter() {
+            for pin in ty.pins.iter() {
+                let pos = ty.anchor_offset.to_point() + pin.pos.to_widget_space().to_vec2();
+                let Size { width, height } = ty.size;
+                assert!(
+                    (0.0..=width).contains(&pos.x) && (0.0..=height).contains(&pos.y),
+                    "pin {} of {} is outside it",
+                    pin.name,
+                    ty.name
+                );
+            }
+        }
+
+        // the new gates take up exactly as much room as the others
+        let named = |name| types.iter().find(|ty| ty.name == name).unwrap();
+        let coords = Coords::new(3, 4);
+        for name in ["xor", "nor", "xnor"] {
+            for orientation in Orientation::ALL {
+                assert_eq!(
+                    named(name).bounding_rect(coords, orientation, false),
+                    named("and").bounding_rect(coords, orientation, false),
+                    "{} facing {}",
+                    name,
+                    orientation
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn restricted_types_only_face_the_ways_they_allow() {
+        let ty = ComponentTypeBuilder::new("sideways")
+            .size(24.0, 48.0)
+            .anchor(12.0, 32.0)
+            .icon_from_str(include_str!("../res/not_gate.svg"))
+            .function(Function::Not)
+            .gate_pins(&["A"], "Y")
+            .orientations(&[Orientation::West, Orientation::East])
+            .build()
+            .unwrap();
+        assert!(ty.allows(Orientation::East) && ty.allows(Orientation::West));
+        assert!(!ty.allows(Orientation::North) && !ty.allows(Orientation::South));
+        // placing it facing a way it can't falls back to the first way it can, in clockwise order
+        assert_eq!(
+            ty.allowed_orientation(Orientation::North),
+            Orientation::East
+        );
+        assert_eq!(ty.allowed_orientation(Orientation::West), Orientation::West);
+        // turning it either way skips the ways it can't face
+        assert_eq!(ty.rotated(Orientation::East, true), Orientation::West);
+        assert_eq!(ty.rotated(Orientation::West, true), Orientation::East);
+        assert_eq!(ty.rotated(Orientation::East, false), Orientation::West);
+
+        let types = [Rc::new(ty)];
+        assert!(Netlist::from_text("component sideways 0 0 west", &types).is_ok());
+        assert!(Netlist::from_text("component sideways 0 0 north", &types).is_err());
+    }
+
+    #[test]
+    fn held_buttons_spring_back_when_a_drag_is_cut_short() {
+        let types = ComponentType::enumerate();
+        let named = |name| types.iter().find(|ty| ty.name == name).unwrap();
+        let mut button = ComponentState::new(
+            Coords::new(0, 0),
+            Rc::clone(named("push-button")),
+            Orientation::North,
+        );
+        let mut switch = ComponentState::new(
+            Coords::new(4, 0),
+            Rc::clone(named("switch")),
+            Orientation::North,
+        );
+        button.instance.value = true;
+        switch.instance.value = true;
+        button.end_drag();
+        switch.end_drag();
+        assert!(!button.instance.value);
+        assert!(switch.instance.value, "a switch stays switched");
+    }
+
+    fn not_gate(orientation: Orientation, mirrored: bool) -> ComponentInstance {
+        let ty = ComponentType::enumerate()
+            .into_iter()
+            .find(|ty| ty.name == "not")
+            .unwrap();
+        let mut instance = ComponentInstance::new(Coords::new(0, 0), ty, orientation);
+        instance.mirrored = mirrored;
+        instance
+    }
+
+    #[test]
+    fn contains_point_follows_orientation() {
+        // 24 by 48 when facing north, with the anchor 12 from the left and 32 from the top
+        let north = not_gate(Orientation::North, false);
+        assert!(north.contains_point(Point::new(0.0, 20.0)));
+        assert!(!north.contains_point(Point::new(30.0, 0.0)));
+        // the top left edges are on it, the bottom right ones are just off it
+        assert!(north.contains_point(Point::new(-4.0, -24.0)));
+        assert!(!north.contains_point(Point::new(20.0, 0.0)));
+
+        // lying on its side, with the anchor 16 from the left
+        let east = not_gate(Orientation::East, false);
+        assert!(east.contains_point(Point::new(30.0, 0.0)));
+        assert!(!east.contains_point(Point::new(0.0, 22.0)));
+        assert!(east.contains_point(Point::new(-8.0, -4.0)));
+        assert!(!east.contains_point(Point::new(40.0, 0.0)));
+
+        // mirroring puts the body on the other side of the anchor
+        let mirrored = not_gate(Orientation::East, true);
+        assert!(mirrored.contains_point(Point::new(-20.0, 0.0)));
+        assert!(!mirrored.contains_point(Point::new(30.0, 0.0)));
+        assert!(mirrored.contains_point(Point::new(-24.0, -4.0)));
+        assert!(!mirrored.contains_point(Point::new(24.0, 0.0)));
+    }
+
+    #[test]
+    fn turned_pins_land_on_grid_intersections() {
+        // gates with extra inputs are wider, which moves their anchor
+        let types = ComponentType::enumerate();
+        let wide_gates = types.iter().flat_map(|ty| {
+            (MIN_GATE_INPUTS + 1..=MAX_GATE_INPUTS).filter_map(move |n| ty.with_inputs(n))
+        });
+        let types: Vec<_> = wide_gates
+            .map(Rc::new)
+            .chain(types.iter().cloned())
+            .collect();
+        for ty in types {
+            for orientation in Orientation::ALL {
+                for mirrored in [false, true] {
+                    let mut instance =
+                        ComponentInstance::new(Coords::new(3, -2), Rc::clone(&ty), orientation);
+                    instance.mirrored = mirrored;
+                    let origin = instance.bounding_rect().origin().to_vec2();
+                    for (i, coords) in instance.resolved_pins().enumerate() {
+                        // where the pin is drawn has to be where wires snap to
+                        let center = instance.pin_center(i) + origin;
+                        assert!(
+                            center.distance(coords.to_canvas_space()) < 1e-9,
+                            "pin {} of {} facing {}, mirrored {}",
+                            instance.pin_name(i),
+                            ty.name,
+                            orientation,
+                            mirrored
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pins_keep_their_names_when_turned() {
+        use crate::sim::{simulate, PinRef};
+
+        let types = ComponentType::enumerate();
+        let named = |name| {
+            let ty = types.iter().find(|ty| ty.name == name).unwrap();
+            Rc::clone(ty)
+        };
+        for orientation in Orientation::ALL {
+            for mirrored in [false, true] {
+                // inverting A makes the gate Y = ¬A · B, which tells its inputs apart
+                let mut gate =
+                    ComponentInstance::new(Coords::new(10, 10), named("and"), orientation);
+                gate.mirrored = mirrored;
+                let a = gate.pin_named("A").unwrap();
+                let b = gate.pin_named("B").unwrap();
+                let y = gate.pin_named("Y").unwrap();
+                assert_eq!(gate.pin_name(a), "A");
+                gate.inverted_inputs.set(a, true);
+                let pins: Vec<_> = gate.resolved_pins().collect();
+
+                for &(a_level, b_level) in
+                    &[(false, false), (false, true), (true, false), (true, true)]
+                {
+                    let mut switch_a = ComponentInstance::new(
+                        Coords::new(0, 0),
+                        named("switch"),
+                        Orientation::North,
+                    );
+                    let mut switch_b = ComponentInstance::new(
+                        Coords::new(0, 4),
+                        named("switch"),
+                        Orientation::North,
+                    );
+                    switch_a.value = a_level;
+                    switch_b.value = b_level;
+                    let wires = vec![(switch_a.coords, pins[a]), (switch_b.coords, pins[b])];
+                    let gate_id = ItemId::next();
+                    let components = vec![
+                        (ItemId::next(), &switch_a),
+                        (ItemId::next(), &switch_b),
+                        (gate_id, &gate),
+                    ];
+                    let levels = simulate(components, wires).unwrap();
+                    assert_eq!(
+                        levels[&PinRef {
+                            component: gate_id,
+                            pin: y
+                        }],
+                        !a_level && b_level,
+                        "facing {}, mirrored {}, A = {}, B = {}",
+                        orientation,
+                        mirrored,
+                        a_level,
+                        b_level
+                    );
+                }
+            }
         }
     }
 }
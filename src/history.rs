@@ -0,0 +1,203 @@
+use druid::{im, Data};
+
+use crate::{
+    canvas::ItemId,
+    component::{ComponentInstance, ComponentState, Function},
+    wire::WireState,
+};
+
+/// How many edits can be undone before the oldest are forgotten
+const MAX_UNDO: usize = 100;
+
+/// The parts of the canvas state that undoing brings back
+#[derive(Clone)]
+pub struct Circuit {
+    pub components: im::OrdMap<ItemId, ComponentState>,
+    pub wires: im::OrdMap<ItemId, WireState>,
+    pub z_order: im::Vector<ItemId>,
+}
+
+impl Circuit {
+    /// Whether nothing has been edited between the two. Selecting things and starting to drag them
+    /// aren't edits
+    fn same(&self, other: &Circuit) -> bool {
+        let same_components =
+            self.components.len() == other.components.len()
+                && self.components.iter().zip(other.components.iter()).all(
+                    |((a_id, a), (b_id, b))| {
+                        a_id == b_id && same_component(&a.instance, &b.instance)
+                    },
+                );
+        let same_wires = self.wires.len() == other.wires.len()
+            && self
+                .wires
+                .iter()
+                .zip(other.wires.iter())
+                .all(|((a_id, a), (b_id, b))| {
+                    a_id == b_id
+                        && a.segments.same(&b.segments)
+                        && a.start == b.start
+                        && a.end == b.end
+                        && a.style.same(&b.style)
+                });
+        same_components && same_wires && self.z_order == other.z_order
+    }
+}
+
+/// Whether the two are the same apart from the level a clock has got to, what clocked components
+/// hold and whether a push button is held down. Clocks flip by themselves and clock everything
+/// else, and buttons spring back up, none of which is an edit
+fn same_component(a: &ComponentInstance, b: &ComponentInstance) -> bool {
+    let springs_back = a.ty().function == Some(Function::Button);
+    if a.ty().function == Some(Function::Clock) || a.ty().is_clocked() || springs_back {
+        let mut a = a.clone();
+        a.value = b.value;
+        a.clock_input = b.clock_input;
+        a.memory = b.memory.clone();
+        a.same(b)
+    } else {
+        a.same(b)
+    }
+}
+
+/// Snapshots of the circuit to go back and forth between. Rather than each edit recording
+/// itself, the circuit is compared with how it was last time it was recorded, so a whole drag
+/// becomes one edit as long as nothing is recorded part way through it
+pub struct History {
+    undo: Vec<Circuit>,
+    redo: Vec<Circuit>,
+    /// The circuit as it was last recorded
+    settled: Option<Circuit>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            settled: None,
+        }
+    }
+
+    /// Makes an edit out of whatever has changed since the last time this was called
+    pub fn record(&mut self, current: Circuit) {
+        if let Some(settled) = self.settled.take() {
+            if !settled.same(&current) {
+                self.undo.push(settled);
+                if self.undo.len() > MAX_UNDO {
+                    self.undo.remove(0);
+                }
+                // the edit starts a new branch, so what was undone can't be redone on top of it
+                self.redo.clear();
+            }
+        }
+        self.settled = Some(current);
+    }
+
+    /// The circuit as it was before the last edit, if there is one to undo
+    pub fn undo(&mut self, current: Circuit) -> Option<Circuit> {
+        self.record(current.clone());
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        self.settled = Some(previous.clone());
+        Some(previous)
+    }
+
+    /// The circuit as it was before the last undo, if nothing has been edited since
+    pub fn redo(&mut self, current: Circuit) -> Option<Circuit> {
+        self.record(current.clone());
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        self.settled = Some(next.clone());
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        canvas::Coords,
+        component::{ComponentType, Orientation},
+        wire::WireSegment,
+    };
+
+    /// A circuit of a single gate at the given coordinates, with the id it was given
+    fn circuit(id: ItemId, coords: Coords) -> Circuit {
+        let ty = ComponentType::enumerate().remove(0);
+        let mut components = im::OrdMap::new();
+        components.insert(id, ComponentState::new(coords, ty, Orientation::North));
+        Circuit {
+            components,
+            wires: im::OrdMap::new(),
+            z_order: im::Vector::unit(id),
+        }
+    }
+
+    fn coords(circuit: &Circuit, id: ItemId) -> Coords {
+        circuit.components[&id].instance.coords
+    }
+
+    /// The circuit with a straight wire added to it between the given coordinates
+    fn with_wire(circuit: &Circuit, id: ItemId, start: Coords, end: Coords) -> Circuit {
+        let segment = WireSegment::new(start, end).unwrap();
+        let mut circuit = circuit.clone();
+        circuit
+            .wires
+            .insert(id, WireState::new(im::Vector::unit(segment)));
+        circuit.z_order.push_back(id);
+        circuit
+    }
+
+    #[test]
+    fn undo_and_redo_a_move() {
+        let id = ItemId::next();
+        let before = circuit(id, Coords::new(1, 2));
+        let mut after = before.clone();
+        after.components.get_mut(&id).unwrap().instance.coords = Coords::new(5, 7);
+
+        let mut history = History::new();
+        history.record(before);
+        history.record(after.clone());
+        let undone = history.undo(after).unwrap();
+        assert_eq!(coords(&undone, id), Coords::new(1, 2));
+        let redone = history.redo(undone).unwrap();
+        assert_eq!(coords(&redone, id), Coords::new(5, 7));
+    }
+
+    #[test]
+    fn wire_edits_are_undone_one_at_a_time() {
+        // wires are part of the circuit snapshots, so they don't need edits of their own
+        let wire = ItemId::next();
+        let empty = circuit(ItemId::next(), Coords::new(0, 0));
+        let added = with_wire(&empty, wire, Coords::new(0, 0), Coords::new(0, 4));
+        let mut restyled = added.clone();
+        restyled.wires.get_mut(&wire).unwrap().style.dashed = true;
+        let mut deleted = restyled.clone();
+        deleted.wires.remove(&wire);
+        deleted.z_order.pop_back();
+
+        let mut history = History::new();
+        history.record(empty);
+        history.record(added);
+        history.record(restyled);
+        history.record(deleted.clone());
+        let undone = history.undo(deleted).unwrap();
+        assert!(undone.wires[&wire].style.dashed);
+        let undone = history.undo(undone).unwrap();
+        assert!(!undone.wires[&wire].style.dashed);
+        let undone = history.undo(undone).unwrap();
+        assert!(undone.wires.is_empty());
+        assert!(history.undo(undone).is_none());
+    }
+
+    #[test]
+    fn recording_no_change_is_not_an_edit() {
+        let id = ItemId::next();
+        let circuit = circuit(id, Coords::new(1, 2));
+        let mut history = History::new();
+        history.record(circuit.clone());
+        history.record(circuit.clone());
+        assert!(history.undo(circuit).is_none());
+    }
+}
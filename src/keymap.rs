@@ -0,0 +1,423 @@
+use std::{collections::HashMap, str::FromStr};
+
+use druid::{keyboard_types::Key, KeyEvent};
+
+use crate::component::Orientation;
+
+/// Environment variable naming a keymap config file to load on startup
+const KEYMAP_VAR: &str = "LOGICISM_KEYMAP";
+/// What the mouse does, which can't be remapped, and how. These are listed along with the key
+/// bindings
+const MOUSE_BINDINGS: [(&str, &str); 5] = [
+    ("zoom", "Ctrl+wheel"),
+    ("pan", "Middle drag"),
+    ("select what a box touches", "Drag rightwards"),
+    ("select what's wholly in a box", "Drag leftwards"),
+    ("add a box to the selection", "Ctrl+drag"),
+];
+/// Config names of the actions swapping the selection to each type, in the order the number keys
+/// pick types
+const SWAP_TYPE_NAMES: [&str; 10] = [
+    "swap-type-1",
+    "swap-type-2",
+    "swap-type-3",
+    "swap-type-4",
+    "swap-type-5",
+    "swap-type-6",
+    "swap-type-7",
+    "swap-type-8",
+    "swap-type-9",
+    "swap-type-0",
+];
+
+/// Something a key can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    HandTool,
+    Face(Orientation),
+    /// Turns the component a quarter turn clockwise from wherever it faces
+    RotateClockwise,
+    RotateCounterClockwise,
+    Mirror,
+    InvertOutput,
+    /// Toggles the bubble on the input pin under the mouse
+    InvertInput,
+    /// Marks the pin under the mouse as deliberately left unconnected, or unmarks it
+    ToggleNoConnect,
+    /// Switches wires dragged out of pins between straight, bent and diagonal
+    ToggleRouting,
+    /// Shows or hides how many wires end on each pin
+    ToggleConnectionCounts,
+    /// Hides the pin markers on every component, or shows them again
+    TogglePins,
+    /// Shows or hides coordinate labels along the edges of the grid
+    ToggleCoordinates,
+    /// Disables the component, or enables it again
+    ToggleEnabled,
+    /// Switches every switch off, leaving clocks and constants as they are
+    ResetSwitches,
+    /// Gives the selected wires the next wire colour
+    CycleWireColor,
+    /// Draws the selected wires dashed, or solid again
+    ToggleDashed,
+    /// Gives the selected gates another input and the selected registers and RAM another bit in
+    /// each word, makes the selected clocks flip twice as often, makes the selected clock
+    /// dividers wait for one more rising edge, and makes the selected delay lines lag one more step
+    Increase,
+    /// Takes the last input off the selected gates and the last bit off the words of the selected
+    /// registers and RAM, along with any wires on them, makes the selected clocks flip half as
+    /// often, makes the selected clock dividers wait for one fewer rising edge, and makes the
+    /// selected delay lines lag one less step
+    Decrease,
+    /// Gives the selected RAM another address bit, doubling the words it holds
+    WidenAddress,
+    /// Takes the last address bit off the selected RAM, along with the top half of its words and
+    /// any wire on the pin
+    NarrowAddress,
+    /// Adds the components wired directly to the selection to it
+    SelectNeighbours,
+    /// Adds everything connected to the selection through any number of wires to it
+    SelectConnected,
+    /// Removes every wire ending on a pin of a selected component
+    Disconnect,
+    /// Removes the selected components and wires, along with the wires ending on the components
+    Delete,
+    /// Copies the selection to the clipboard as a netlist
+    Copy,
+    /// Copies a CSV of which pins of the selection are wired to which
+    CopyPinMap,
+    /// Adds the netlist on the clipboard to the circuit
+    Paste,
+    /// Copies the truth table of the selection, from its switches and push buttons to its LEDs, as
+    /// CSV
+    CopyTruthTable,
+    /// Copies the words held by the selected RAM as CSV
+    CopyMemory,
+    /// Changes the selected components to the type with this index, the one its number key places
+    SwapType(usize),
+    Undo,
+    Redo,
+    /// Asks where to save the circuit, and saves it there
+    Save,
+    /// Asks for a circuit file, and replaces the circuit with the one in it
+    Open,
+    /// Draws the selection above every other component
+    BringToFront,
+    /// Draws the selection below every other component
+    SendToBack,
+    /// Goes back to 1× zoom with the grid origin in the top left corner
+    ResetView,
+    /// Zooms and pans so the selection fills the canvas, or everything if nothing is selected
+    ZoomToSelection,
+    /// Lists every key binding until the next key press or click
+    ShowHelp,
+    Cancel,
+}
+
+impl Action {
+    const ALL: [Action; 52] = [
+        Action::HandTool,
+        Action::Face(Orientation::North),
+        Action::Face(Orientation::East),
+        Action::Face(Orientation::South),
+        Action::Face(Orientation::West),
+        Action::RotateClockwise,
+        Action::RotateCounterClockwise,
+        Action::Mirror,
+        Action::InvertOutput,
+        Action::InvertInput,
+        Action::ToggleNoConnect,
+        Action::ToggleRouting,
+        Action::ToggleConnectionCounts,
+        Action::TogglePins,
+        Action::ToggleCoordinates,
+        Action::ToggleEnabled,
+        Action::ResetSwitches,
+        Action::CycleWireColor,
+        Action::ToggleDashed,
+        Action::Increase,
+        Action::Decrease,
+        Action::WidenAddress,
+        Action::NarrowAddress,
+        Action::SelectNeighbours,
+        Action::SelectConnected,
+        Action::Disconnect,
+        Action::Delete,
+        Action::Copy,
+        Action::CopyPinMap,
+        Action::Paste,
+        Action::CopyTruthTable,
+        Action::CopyMemory,
+        Action::SwapType(0),
+        Action::SwapType(1),
+        Action::SwapType(2),
+        Action::SwapType(3),
+        Action::SwapType(4),
+        Action::SwapType(5),
+        Action::SwapType(6),
+        Action::SwapType(7),
+        Action::SwapType(8),
+        Action::SwapType(9),
+        Action::Undo,
+        Action::Redo,
+        Action::Save,
+        Action::Open,
+        Action::BringToFront,
+        Action::SendToBack,
+        Action::ResetView,
+        Action::ZoomToSelection,
+        Action::ShowHelp,
+        Action::Cancel,
+    ];
+
+    /// The name of the action in keymap config files
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::HandTool => "hand-tool",
+            Action::Face(Orientation::North) => "face-north",
+            Action::Face(Orientation::East) => "face-east",
+            Action::Face(Orientation::South) => "face-south",
+            Action::Face(Orientation::West) => "face-west",
+            Action::RotateClockwise => "rotate-clockwise",
+            Action::RotateCounterClockwise => "rotate-counter-clockwise",
+            Action::Mirror => "mirror",
+            Action::InvertOutput => "invert-output",
+            Action::InvertInput => "invert-input",
+            Action::ToggleNoConnect => "toggle-no-connect",
+            Action::ToggleRouting => "toggle-routing",
+            Action::ToggleConnectionCounts => "toggle-connection-counts",
+            Action::TogglePins => "toggle-pins",
+            Action::ToggleCoordinates => "toggle-coordinates",
+            Action::ToggleEnabled => "toggle-enabled",
+            Action::ResetSwitches => "reset-switches",
+            Action::CycleWireColor => "cycle-wire-color",
+            Action::ToggleDashed => "toggle-dashed",
+            Action::Increase => "increase",
+            Action::Decrease => "decrease",
+            Action::WidenAddress => "more-address-bits",
+            Action::NarrowAddress => "fewer-address-bits",
+            Action::SelectNeighbours => "select-neighbours",
+            Action::SelectConnected => "select-connected",
+            Action::Disconnect => "disconnect",
+            Action::Delete => "delete",
+            Action::Copy => "copy",
+            Action::CopyPinMap => "copy-pin-map",
+            Action::Paste => "paste",
+            Action::CopyTruthTable => "copy-truth-table",
+            Action::CopyMemory => "copy-memory",
+            Action::SwapType(n) => SWAP_TYPE_NAMES[n],
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Save => "save",
+            Action::Open => "open",
+            Action::BringToFront => "bring-to-front",
+            Action::SendToBack => "send-to-back",
+            Action::ResetView => "reset-view",
+            Action::ZoomToSelection => "zoom-to-selection",
+            Action::ShowHelp => "show-help",
+            Action::Cancel => "cancel",
+        }
+    }
+}
+
+/// A key, and whether Ctrl is held with it. Shift is part of the key, since it changes which
+/// character it gives
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Binding {
+    ctrl: bool,
+    key: Key,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Binding, Action>,
+}
+
+impl Keymap {
+    /// Loads the config file named by `LOGICISM_KEYMAP`, falling back to the default bindings if
+    /// it isn't set or can't be read
+    pub fn load() -> Self {
+        let path = match std::env::var_os(KEYMAP_VAR) {
+            Some(path) => path,
+            None => return Keymap::default(),
+        };
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|config| Keymap::from_config(&config));
+        match result {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                eprintln!("couldn't load keymap {}: {}", path.to_string_lossy(), e);
+                Keymap::default()
+            },
+        }
+    }
+
+    /// Parses lines like `face-north = w` and applies them over the defaults. Named keys use
+    /// their web names (`Escape`, `Delete`), and `Space` means the space bar. Keys pressed with
+    /// Ctrl start with `Ctrl+`, like `undo = Ctrl+z`. Empty lines and lines starting with `#` are
+    /// skipped
+    pub fn from_config(config: &str) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, key) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `action = key`", i + 1))?;
+            let (name, key) = (name.trim(), key.trim());
+            let action = Action::ALL
+                .iter()
+                .copied()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| format!("line {}: unknown action `{}`", i + 1, name))?;
+            let (ctrl, key) = match key.strip_prefix("Ctrl+") {
+                Some(key) => (true, key),
+                None => (false, key),
+            };
+            let key = match key {
+                "Space" => Key::Character(" ".to_owned()),
+                key => Key::from_str(key)
+                    .map_err(|_| format!("line {}: unknown key `{}`", i + 1, key))?,
+            };
+            // the new key replaces the default one rather than adding to it
+            keymap.bindings.retain(|_, bound| *bound != action);
+            keymap.bindings.insert(Binding { ctrl, key }, action);
+        }
+        Ok(keymap)
+    }
+
+    pub fn action(&self, event: &KeyEvent) -> Option<Action> {
+        let binding = Binding {
+            ctrl: event.mods.ctrl(),
+            key: event.key.clone(),
+        };
+        self.bindings.get(&binding).copied()
+    }
+
+    /// Every bound key with its action's config name, in the order actions are declared, followed
+    /// by what the mouse does. Keys are named the way config files name them
+    pub fn describe(&self) -> Vec<(&'static str, String)> {
+        let mouse = MOUSE_BINDINGS
+            .iter()
+            .map(|(name, gesture)| (*name, (*gesture).to_owned()));
+        Action::ALL
+            .iter()
+            .flat_map(|action| {
+                self.bindings
+                    .iter()
+                    .filter(move |(_, bound)| *bound == action)
+                    .map(move |(binding, _)| {
+                        let key = match binding.key {
+                            Key::Character(ref s) if s == " " => "Space".to_owned(),
+                            ref key => key.to_string(),
+                        };
+                        let ctrl = if binding.ctrl { "Ctrl+" } else { "" };
+                        (action.name(), format!("{}{}", ctrl, key))
+                    })
+            })
+            .chain(mouse)
+            .collect()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let key = |key: Key| Binding { ctrl: false, key };
+        let character = |s: &str| key(Key::Character(s.to_owned()));
+        let ctrl = |s: &str| Binding {
+            ctrl: true,
+            key: Key::Character(s.to_owned()),
+        };
+        let bindings = [
+            (character(" "), Action::HandTool),
+            (character("w"), Action::Face(Orientation::North)),
+            (character("d"), Action::Face(Orientation::East)),
+            (character("s"), Action::Face(Orientation::South)),
+            (character("a"), Action::Face(Orientation::West)),
+            (character("r"), Action::RotateClockwise),
+            (character("R"), Action::RotateCounterClockwise),
+            (character("f"), Action::Mirror),
+            (character("i"), Action::InvertOutput),
+            (character("n"), Action::InvertInput),
+            (character("x"), Action::ToggleNoConnect),
+            (character("b"), Action::ToggleRouting),
+            (character("c"), Action::ToggleConnectionCounts),
+            (character("p"), Action::TogglePins),
+            (character("#"), Action::ToggleCoordinates),
+            (character("e"), Action::ToggleEnabled),
+            (character("o"), Action::ResetSwitches),
+            (character("h"), Action::CycleWireColor),
+            (character("H"), Action::ToggleDashed),
+            (character("+"), Action::Increase),
+            (character("-"), Action::Decrease),
+            (character(">"), Action::WidenAddress),
+            (character("<"), Action::NarrowAddress),
+            (character("g"), Action::SelectNeighbours),
+            (character("G"), Action::SelectConnected),
+            (character("D"), Action::Disconnect),
+            (key(Key::Delete), Action::Delete),
+            (key(Key::Backspace), Action::Delete),
+            (ctrl("c"), Action::Copy),
+            (ctrl("C"), Action::CopyPinMap),
+            (ctrl("v"), Action::Paste),
+            (character("t"), Action::CopyTruthTable),
+            (character("m"), Action::CopyMemory),
+            (ctrl("z"), Action::Undo),
+            (ctrl("y"), Action::Redo),
+            (ctrl("Z"), Action::Redo),
+            (ctrl("s"), Action::Save),
+            (ctrl("o"), Action::Open),
+            (character("]"), Action::BringToFront),
+            (character("["), Action::SendToBack),
+            (key(Key::Home), Action::ResetView),
+            (character("z"), Action::ZoomToSelection),
+            (character("?"), Action::ShowHelp),
+            (key(Key::Escape), Action::Cancel),
+        ];
+        // ctrl and a number key, in the order the keys pick types
+        let swap_types = (0..10).map(|n| (ctrl(&((n + 1) % 10).to_string()), Action::SwapType(n)));
+        Keymap {
+            bindings: bindings.iter().cloned().chain(swap_types).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Modifiers;
+
+    use super::*;
+
+    fn action(keymap: &Keymap, mods: Modifiers, key: &str) -> Option<Action> {
+        keymap.action(&KeyEvent::for_test(mods, key))
+    }
+
+    #[test]
+    fn ctrl_keys_are_bound_apart_from_plain_ones() {
+        let keymap = Keymap::default();
+        let ctrl = Modifiers::CONTROL;
+        assert_eq!(action(&keymap, ctrl, "s"), Some(Action::Save));
+        assert_eq!(
+            action(&keymap, Modifiers::empty(), "s"),
+            Some(Action::Face(Orientation::South))
+        );
+        assert_eq!(action(&keymap, ctrl, "Z"), Some(Action::Redo));
+        assert_eq!(action(&keymap, ctrl, "0"), Some(Action::SwapType(9)));
+    }
+
+    #[test]
+    fn ctrl_keys_can_be_remapped() {
+        let keymap = Keymap::from_config("save = Ctrl+w\nincrease = +").unwrap();
+        let ctrl = Modifiers::CONTROL;
+        assert_eq!(action(&keymap, ctrl, "w"), Some(Action::Save));
+        assert_eq!(action(&keymap, ctrl, "s"), None);
+        assert_eq!(
+            action(&keymap, Modifiers::empty(), "+"),
+            Some(Action::Increase)
+        );
+        let described = keymap.describe();
+        assert!(described.contains(&("save", "Ctrl+w".to_owned())));
+    }
+}
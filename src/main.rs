@@ -5,24 +5,36 @@ use druid::{Affine, AppLauncher, Widget, WindowDesc};
 
 mod canvas;
 mod component;
+mod history;
+mod keymap;
+mod netlist;
+mod sim;
+mod theme;
 mod wire;
 
 use canvas::{Canvas, CanvasState};
+use keymap::Keymap;
 
 const IDENTITY: Affine = Affine::scale(1.0);
 
 fn main() {
     let component_types = Rc::new(ComponentType::enumerate());
+    let keymap = Rc::new(Keymap::load());
 
-    let window = WindowDesc::new(move || root_widget(Rc::clone(&component_types)))
-        .title("Logicism")
-        .window_size((800.0, 600.0));
+    let window =
+        WindowDesc::new(move || root_widget(Rc::clone(&component_types), Rc::clone(&keymap)))
+            .title("Logicism")
+            .window_size((800.0, 600.0));
 
     AppLauncher::with_window(window)
+        .configure_env(|env, _| theme::configure_env(env))
         .launch(CanvasState::new())
         .expect("Failed to launch application");
 }
 
-fn root_widget(component_icons: Rc<Vec<Rc<ComponentType>>>) -> impl Widget<CanvasState> {
-    Canvas::new(component_icons)
+fn root_widget(
+    component_icons: Rc<Vec<Rc<ComponentType>>>,
+    keymap: Rc<Keymap>,
+) -> impl Widget<CanvasState> {
+    Canvas::new(component_icons, keymap)
 }
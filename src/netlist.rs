@@ -0,0 +1,656 @@
+use std::{fmt::Write, path::Path, rc::Rc, time::Duration};
+
+use druid::{im, Color, FileSpec, Point};
+
+use crate::{
+    canvas::Coords,
+    component::{
+        ComponentInstance, ComponentType, Function, Orientation, PinType, DEFAULT_ADDRESS_WIDTH,
+        DEFAULT_CLOCK_PERIOD, DEFAULT_DELAY, DEFAULT_DIVISOR, DEFAULT_WORD_WIDTH, MAX_CLOCK_PERIOD,
+        MAX_DELAY, MAX_DIVISOR, MIN_CLOCK_PERIOD, MIN_GATE_INPUTS,
+    },
+    wire::{WireEnd, WireSegment, WireState},
+};
+
+/// How far from the origin pasted coordinates can be. This is far beyond any canvas, and small
+/// enough that moving a netlist around can't overflow
+const MAX_COORD: isize = 1 << 24;
+
+/// Grid cells between the columns of auto-placed parts, leaving room for wires between them
+const PLACEMENT_COLUMN_SPACING: isize = 6;
+/// Grid cells between the rows of auto-placed parts in a column
+const PLACEMENT_ROW_SPACING: isize = 4;
+
+/// The first line of every netlist written, followed by the format version. Netlists without it,
+/// from before it was written or typed by hand, are read as the current version
+const HEADER: &str = "# logicism";
+/// Goes up whenever netlists are written in a way older versions would misread
+const FORMAT_VERSION: u32 = 1;
+
+/// Saved circuits are netlists, in the same text format the clipboard uses
+pub const CIRCUIT_FILE_TYPE: FileSpec = FileSpec::new("Logicism circuit", &["logicism"]);
+
+/// Part of a circuit in a form that can go through the clipboard as text
+pub struct Netlist {
+    pub components: Vec<ComponentInstance>,
+    pub wires: Vec<WireState>,
+}
+
+impl Netlist {
+    /// Writes one item per line, like `component and 3 4 east inputs:3 inverted-input:0` or
+    /// `wire 3,2 3,6 7,6 color:d04040 dashed`. Wires are written as the points they pass through
+    /// followed by their style, and what a register or RAM holds as its bits, word by word in pin
+    /// order, like `holds:0110`
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", HEADER, FORMAT_VERSION);
+        for instance in self.components.iter() {
+            let Coords { x, y } = instance.coords;
+            write!(
+                text,
+                "component {} {} {} {}",
+                instance.ty().name,
+                x,
+                y,
+                instance.orientation
+            )
+            .unwrap();
+            let inputs = instance.ty().input_count();
+            if instance.ty().has_variable_inputs() && inputs != MIN_GATE_INPUTS {
+                write!(text, " inputs:{}", inputs).unwrap();
+            }
+            let width = instance.ty().output_count();
+            if instance.ty().holds_words() && width != DEFAULT_WORD_WIDTH {
+                write!(text, " width:{}", width).unwrap();
+            }
+            let address_width = instance.ty().address_width();
+            if instance.ty().function == Some(Function::Ram)
+                && address_width != DEFAULT_ADDRESS_WIDTH
+            {
+                write!(text, " address-width:{}", address_width).unwrap();
+            }
+            if instance.ty().function == Some(Function::Divider)
+                && instance.divisor != DEFAULT_DIVISOR
+            {
+                write!(text, " divisor:{}", instance.divisor).unwrap();
+            }
+            if instance.ty().function == Some(Function::Delay) && instance.delay != DEFAULT_DELAY {
+                write!(text, " delay:{}", instance.delay).unwrap();
+            }
+            if instance.mirrored {
+                text.push_str(" mirrored");
+            }
+            if instance.output_inverted {
+                text.push_str(" inverted");
+            }
+            for (pin, _) in instance
+                .inverted_inputs
+                .iter()
+                .enumerate()
+                .filter(|(_, i)| **i)
+            {
+                write!(text, " inverted-input:{}", pin).unwrap();
+            }
+            for (pin, _) in instance
+                .no_connect
+                .iter()
+                .enumerate()
+                .filter(|(_, nc)| **nc)
+            {
+                write!(text, " no-connect:{}", pin).unwrap();
+            }
+            if !instance.enabled {
+                text.push_str(" disabled");
+            }
+            if instance.value {
+                text.push_str(" on");
+            }
+            if instance.memory.iter().any(|bit| *bit) {
+                let bits: String = instance
+                    .memory
+                    .iter()
+                    .map(|bit| if *bit { '1' } else { '0' })
+                    .collect();
+                write!(text, " holds:{}", bits).unwrap();
+            }
+            if instance.ty().function == Some(Function::Clock) {
+                if instance.period != DEFAULT_CLOCK_PERIOD {
+                    write!(text, " period:{}", instance.period.as_millis()).unwrap();
+                }
+                if instance.paused {
+                    text.push_str(" paused");
+                }
+            }
+            text.push('\n');
+        }
+        for wire in self.wires.iter() {
+            text.push_str("wire");
+            for Coords { x, y } in wire_points(wire) {
+                write!(text, " {},{}", x, y).unwrap();
+            }
+            if let Some(ref color) = wire.style.color {
+                let (r, g, b, _) = color.as_rgba8();
+                write!(text, " color:{:02x}{:02x}{:02x}", r, g, b).unwrap();
+            }
+            if wire.style.dashed {
+                text.push_str(" dashed");
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn save_to_path(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_text()).map_err(|e| e.to_string())
+    }
+
+    /// Reads a file written by `save_to_path`. Fails if it uses a component type that no longer
+    /// exists, like pasting would
+    pub fn load_from_path(path: &Path, types: &[Rc<ComponentType>]) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Netlist::from_text(&text, types)
+    }
+
+    /// Lists every pin of every component as CSV, one pin per line after a header. Components are
+    /// numbered in the order they're listed in, and pins are at their grid coordinates on the
+    /// canvas
+    pub fn pin_map(&self) -> String {
+        let mut csv = "component,type,pin,direction,x,y\n".to_owned();
+        for (i, instance) in self.components.iter().enumerate() {
+            for (pin, Coords { x, y }) in instance.resolved_pins().enumerate() {
+                let direction = match instance.pin_type(pin) {
+                    PinType::Input => "input",
+                    PinType::Output => "output",
+                };
+                writeln!(
+                    csv,
+                    "{},{},{},{},{},{}",
+                    i,
+                    instance.ty().name,
+                    instance.pin_name(pin),
+                    direction,
+                    x,
+                    y
+                )
+                .unwrap();
+            }
+        }
+        csv
+    }
+
+    /// Reads text written by `to_text`. Empty lines and lines starting with `#` are skipped, apart
+    /// from a header giving a format version newer than this one, which is refused.
+    ///
+    /// Circuits can also be given as a plain list of parts and connections, like `part g1 and` and
+    /// `connect g1.Y g2.A`, which are placed and wired automatically. Parts are laid out left to
+    /// right in columns by how many gates drive them, below anything placed explicitly
+    pub fn from_text(text: &str, types: &[Rc<ComponentType>]) -> Result<Self, String> {
+        let mut netlist = Netlist {
+            components: Vec::new(),
+            wires: Vec::new(),
+        };
+        let mut parts: Vec<(&str, ComponentInstance)> = Vec::new();
+        // the line each was on, for errors, and its two `part.pin` ends
+        let mut connections: Vec<(usize, &str, &str)> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let err = |msg: String| format!("line {}: {}", i + 1, msg);
+            // only the first line can be the header, so later comments can say anything
+            if let Some(version) = line.strip_prefix(HEADER).filter(|_| i == 0) {
+                let version = version.trim();
+                if !matches!(version.parse::<u32>(), Ok(v) if v <= FORMAT_VERSION) {
+                    return Err(err(format!(
+                        "written in format version {}, and this version only reads up to {}",
+                        version, FORMAT_VERSION
+                    )));
+                }
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("component") => {
+                    let name = words.next().ok_or_else(|| err("missing type".to_owned()))?;
+                    let ty = types
+                        .iter()
+                        .find(|ty| ty.name == name)
+                        .ok_or_else(|| err(format!("unknown component type `{}`", name)))?;
+                    let mut number = || -> Result<isize, String> {
+                        let word = words
+                            .next()
+                            .ok_or_else(|| err("missing coords".to_owned()))?;
+                        word.parse()
+                            .ok()
+                            .filter(|n: &isize| (-MAX_COORD..=MAX_COORD).contains(n))
+                            .ok_or_else(|| err(format!("expected a coordinate, got `{}`", word)))
+                    };
+                    let coords = Coords::new(number()?, number()?);
+                    let orientation = words
+                        .next()
+                        .ok_or_else(|| err("missing orientation".to_owned()))?
+                        .parse::<Orientation>()
+                        .map_err(err)?;
+                    if !ty.allows(orientation) {
+                        return Err(err(format!("`{}` can't face {}", name, orientation)));
+                    }
+                    let mut instance = ComponentInstance::new(coords, Rc::clone(ty), orientation);
+                    for flag in words {
+                        match flag {
+                            "mirrored" => instance.mirrored = true,
+                            // like the invert key, which leaves gates already drawn with a bubble
+                            "inverted" if !instance.ty().has_output_bubble() => {
+                                instance.output_inverted = true
+                            },
+                            "inverted" => {
+                                return Err(err(format!("`{}` can't be inverted", name)));
+                            },
+                            "disabled" => instance.enabled = false,
+                            "on" => instance.value = true,
+                            "paused" => instance.paused = true,
+                            flag if flag.starts_with("period:") => {
+                                instance.period = flag["period:".len()..]
+                                    .parse()
+                                    .ok()
+                                    .map(Duration::from_millis)
+                                    .filter(|period| {
+                                        (MIN_CLOCK_PERIOD..=MAX_CLOCK_PERIOD).contains(period)
+                                    })
+                                    .ok_or_else(|| err(format!("bad period in `{}`", flag)))?;
+                            },
+                            flag if flag.starts_with("divisor:") => {
+                                instance.divisor = flag["divisor:".len()..]
+                                    .parse()
+                                    .ok()
+                                    .filter(|divisor| (1..=MAX_DIVISOR).contains(divisor))
+                                    .filter(|_| instance.ty().function == Some(Function::Divider))
+                                    .ok_or_else(|| {
+                                        err(format!("`{}` can't have {}", name, flag))
+                                    })?;
+                            },
+                            flag if flag.starts_with("delay:") => {
+                                instance.delay = flag["delay:".len()..]
+                                    .parse()
+                                    .ok()
+                                    .filter(|delay| (1..=MAX_DELAY).contains(delay))
+                                    .filter(|_| instance.ty().function == Some(Function::Delay))
+                                    .ok_or_else(|| {
+                                        err(format!("`{}` can't have {}", name, flag))
+                                    })?;
+                            },
+                            flag if flag.starts_with("inputs:") => {
+                                let inputs = flag["inputs:".len()..].parse::<usize>().ok();
+                                let fits = matches!(inputs, Some(n) if n == instance.ty().input_count()
+                                    || instance.set_input_count(n));
+                                if !fits {
+                                    return Err(err(format!("`{}` can't have {}", name, flag)));
+                                }
+                            },
+                            flag if flag.starts_with("width:") => {
+                                let width = flag["width:".len()..].parse::<usize>().ok();
+                                let fits = matches!(width, Some(n) if n == instance.ty().output_count()
+                                    && instance.ty().holds_words()
+                                    || instance.set_width(n));
+                                if !fits {
+                                    return Err(err(format!("`{}` can't have {}", name, flag)));
+                                }
+                            },
+                            flag if flag.starts_with("address-width:") => {
+                                let width = flag["address-width:".len()..].parse::<usize>().ok();
+                                let fits = matches!(width, Some(n) if n == instance.ty().address_width()
+                                    && instance.ty().function == Some(Function::Ram)
+                                    || instance.set_address_width(n));
+                                if !fits {
+                                    return Err(err(format!("`{}` can't have {}", name, flag)));
+                                }
+                            },
+                            // after the widths, which decide how many bits there are
+                            flag if flag.starts_with("holds:") => {
+                                let bits = &flag["holds:".len()..];
+                                let memory = bits
+                                    .chars()
+                                    .map(|c| match c {
+                                        '0' => Some(false),
+                                        '1' => Some(true),
+                                        _ => None,
+                                    })
+                                    .collect::<Option<im::Vector<_>>>()
+                                    .filter(|memory| memory.len() == instance.memory.len())
+                                    .ok_or_else(|| err(format!("bad bits in `{}`", flag)))?;
+                                instance.memory = memory;
+                            },
+                            flag if flag.starts_with("inverted-input:") => {
+                                let pin = flag["inverted-input:".len()..]
+                                    .parse::<usize>()
+                                    .ok()
+                                    .filter(|pin| *pin < instance.inverted_inputs.len())
+                                    .filter(|pin| instance.pin_type(*pin) == PinType::Input)
+                                    .ok_or_else(|| err(format!("bad pin in `{}`", flag)))?;
+                                instance.inverted_inputs.set(pin, true);
+                            },
+                            flag if flag.starts_with("no-connect:") => {
+                                let pin = flag["no-connect:".len()..]
+                                    .parse::<usize>()
+                                    .ok()
+                                    .filter(|pin| *pin < instance.no_connect.len())
+                                    .ok_or_else(|| err(format!("bad pin in `{}`", flag)))?;
+                                instance.no_connect.set(pin, true);
+                            },
+                            flag => return Err(err(format!("unknown flag `{}`", flag))),
+                        }
+                    }
+                    netlist.components.push(instance);
+                },
+                Some("wire") => {
+                    let mut words = words.peekable();
+                    // the style comes after the points, and flags never start with a digit or sign
+                    let mut points = Vec::new();
+                    while let Some(word) =
+                        words.next_if(|word| !word.starts_with(char::is_alphabetic))
+                    {
+                        points.push(
+                            parse_point(word)
+                                .ok_or_else(|| err(format!("bad point `{}`", word)))?,
+                        );
+                    }
+                    if points.len() < 2 {
+                        return Err(err("a wire needs at least two points".to_owned()));
+                    }
+                    let segments = points
+                        .windows(2)
+                        .map(|pair| {
+                            WireSegment::new(pair[0], pair[1]).ok_or_else(|| {
+                                err("wire segments must be straight or diagonal".to_owned())
+                            })
+                        })
+                        .collect::<Result<im::Vector<_>, _>>()?;
+                    let mut wire = WireState::new(segments);
+                    for flag in words {
+                        match flag {
+                            "dashed" => wire.style.dashed = true,
+                            flag if flag.starts_with("color:") => {
+                                let hex = &flag["color:".len()..];
+                                let rgb = u32::from_str_radix(hex, 16)
+                                    .ok()
+                                    .filter(|_| hex.len() == 6)
+                                    .ok_or_else(|| err(format!("bad colour in `{}`", flag)))?;
+                                wire.style.color = Some(Color::from_rgba32_u32(rgb << 8 | 0xff));
+                            },
+                            flag => return Err(err(format!("unknown flag `{}`", flag))),
+                        }
+                    }
+                    netlist.wires.push(wire);
+                },
+                Some("part") => {
+                    let name = words.next().ok_or_else(|| err("missing name".to_owned()))?;
+                    if parts.iter().any(|(other, _)| *other == name) {
+                        return Err(err(format!("part `{}` is already defined", name)));
+                    }
+                    let ty_name = words.next().ok_or_else(|| err("missing type".to_owned()))?;
+                    let ty = types
+                        .iter()
+                        .find(|ty| ty.name == ty_name)
+                        .ok_or_else(|| err(format!("unknown component type `{}`", ty_name)))?;
+                    // facing east, inputs are on the left and outputs on the right
+                    let orientation = ty.allowed_orientation(Orientation::East);
+                    let instance =
+                        ComponentInstance::new(Coords::new(0, 0), Rc::clone(ty), orientation);
+                    parts.push((name, instance));
+                },
+                Some("connect") => match (words.next(), words.next(), words.next()) {
+                    (Some(a), Some(b), None) => connections.push((i + 1, a, b)),
+                    _ => return Err(err("expected two pins to connect".to_owned())),
+                },
+                Some(word) => return Err(err(format!("unknown item `{}`", word))),
+                None => unreachable!(),
+            }
+        }
+        if !parts.is_empty() {
+            netlist.place_parts(parts, &connections)?;
+        }
+        Ok(netlist)
+    }
+
+    /// Lays out auto-placed parts and routes the wires between them. Each part goes one column to
+    /// the right of the furthest part driving it, so signals flow left to right
+    fn place_parts(
+        &mut self,
+        mut parts: Vec<(&str, ComponentInstance)>,
+        connections: &[(usize, &str, &str)],
+    ) -> Result<(), String> {
+        let mut pins = Vec::new();
+        for (line, a, b) in connections.iter() {
+            let err = |msg: String| format!("line {}: {}", line, msg);
+            let pin = |end: &str| {
+                let (part, pin) = end
+                    .split_once('.')
+                    .ok_or_else(|| err(format!("expected `part.pin`, got `{}`", end)))?;
+                let index = parts
+                    .iter()
+                    .position(|(name, _)| *name == part)
+                    .ok_or_else(|| err(format!("unknown part `{}`", part)))?;
+                let pin = parts[index]
+                    .1
+                    .pin_named(pin)
+                    .ok_or_else(|| err(format!("`{}` has no pin `{}`", part, pin)))?;
+                Ok::<_, String>((index, pin))
+            };
+            pins.push((pin(a)?, pin(b)?));
+        }
+
+        // longest chain of drivers into each part. a loop would go on forever, so a part can't be
+        // pushed further right than there are parts
+        let mut depth = vec![0; parts.len()];
+        for _ in 0..parts.len() {
+            for &((a, a_pin), (b, b_pin)) in pins.iter() {
+                let driven = match (parts[a].1.pin_type(a_pin), parts[b].1.pin_type(b_pin)) {
+                    (PinType::Output, _) => Some((a, b)),
+                    (_, PinType::Output) => Some((b, a)),
+                    _ => None,
+                };
+                if let Some((driver, driven)) = driven {
+                    depth[driven] = depth[driven].max(depth[driver] + 1).min(parts.len());
+                }
+            }
+        }
+
+        // a couple of cells below anything placed explicitly
+        let top = self
+            .components
+            .iter()
+            .map(|instance| instance.bounding_rect())
+            .chain(self.wires.iter().map(WireState::bounding_rect))
+            .map(|rect| Coords::from_canvas_space(Point::new(rect.x0, rect.y1)).y + 2)
+            .max()
+            .unwrap_or(0);
+        let mut rows = vec![0; parts.len() + 1];
+        for ((_, instance), depth) in parts.iter_mut().zip(depth) {
+            instance.coords = Coords::new(
+                depth as isize * PLACEMENT_COLUMN_SPACING,
+                top + rows[depth] * PLACEMENT_ROW_SPACING,
+            );
+            rows[depth] += 1;
+        }
+
+        let obstacles: Vec<_> = parts
+            .iter()
+            .map(|(_, instance)| instance.bounding_rect())
+            .collect();
+        for &((a, a_pin), (b, b_pin)) in pins.iter() {
+            let start = parts[a].1.resolved_pins().nth(a_pin).unwrap();
+            let end = parts[b].1.resolved_pins().nth(b_pin).unwrap();
+            if let Some(wire) = WireState::routed(start, end, &obstacles) {
+                self.wires.push(wire);
+            }
+        }
+        self.components
+            .extend(parts.into_iter().map(|(_, instance)| instance));
+        Ok(())
+    }
+
+    /// The smallest x and y of any component anchor or wire point, or `None` if the netlist is
+    /// empty
+    pub fn top_left(&self) -> Option<Coords> {
+        self.components
+            .iter()
+            .map(|instance| instance.coords)
+            .chain(self.wires.iter().flat_map(wire_points))
+            .reduce(|a, b| Coords::new(a.x.min(b.x), a.y.min(b.y)))
+    }
+
+    /// Moves everything so that the top left of the netlist ends up at `coords`
+    pub fn move_to(&mut self, coords: Coords) {
+        let top_left = match self.top_left() {
+            Some(top_left) => top_left,
+            None => return,
+        };
+        let (dx, dy) = (coords.x - top_left.x, coords.y - top_left.y);
+        let shift = |c: Coords| Coords::new(c.x + dx, c.y + dy);
+        for instance in self.components.iter_mut() {
+            instance.coords = shift(instance.coords);
+        }
+        for wire in self.wires.iter_mut() {
+            wire.segments = wire
+                .segments
+                .iter()
+                // unwrap: shifting both ends keeps the segment's direction
+                .map(|segment| {
+                    WireSegment::new(shift(segment.start()), shift(segment.end())).unwrap()
+                })
+                .collect();
+            for end in [&mut wire.start, &mut wire.end] {
+                if let WireEnd::Free(coords) = end {
+                    *coords = shift(*coords);
+                }
+            }
+        }
+    }
+}
+
+/// The start of a wire followed by the end of each of its segments
+fn wire_points(wire: &WireState) -> impl Iterator<Item = Coords> + '_ {
+    wire.segments
+        .front()
+        .map(WireSegment::start)
+        .into_iter()
+        .chain(wire.segments.iter().map(WireSegment::end))
+}
+
+fn parse_point(word: &str) -> Option<Coords> {
+    let (x, y) = word.split_once(',')?;
+    let coordinate = |n: &str| {
+        n.parse()
+            .ok()
+            .filter(|n| (-MAX_COORD..=MAX_COORD).contains(n))
+    };
+    Some(Coords::new(coordinate(x)?, coordinate(y)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text `to_text` writes for a netlist with these lines
+    fn with_header(lines: &str) -> String {
+        format!("{} {}\n{}", HEADER, FORMAT_VERSION, lines)
+    }
+
+    #[test]
+    fn far_out_coordinates_are_refused() {
+        let types = ComponentType::enumerate();
+        let furthest = format!("component and {} {} north", MAX_COORD, -MAX_COORD);
+        assert!(Netlist::from_text(&furthest, &types).is_ok());
+        let too_far = format!("component and {} 0 north", MAX_COORD + 1);
+        assert!(Netlist::from_text(&too_far, &types).is_err());
+        let wire = format!("wire 0,0 0,{}", isize::MAX);
+        assert!(Netlist::from_text(&wire, &types).is_err());
+    }
+
+    #[test]
+    fn register_width_and_bits_round_trip() {
+        let types = ComponentType::enumerate();
+        let text = "component register 0 0 north width:6 holds:101100\n";
+        let netlist = Netlist::from_text(text, &types).unwrap();
+        assert_eq!(netlist.components[0].ty().output_count(), 6);
+        assert_eq!(netlist.to_text(), with_header(text));
+        // the bits have to fit the width
+        let short = "component register 0 0 north width:6 holds:1011";
+        assert!(Netlist::from_text(short, &types).is_err());
+        let too_wide = "component register 0 0 north width:9";
+        assert!(Netlist::from_text(too_wide, &types).is_err());
+    }
+
+    #[test]
+    fn ram_widths_and_words_round_trip() {
+        let types = ComponentType::enumerate();
+        // two words of two bits, then the word last read
+        let text = "component ram-sync 0 0 north width:2 address-width:1 holds:100111\n";
+        let netlist = Netlist::from_text(text, &types).unwrap();
+        assert_eq!(netlist.components[0].words(), [0b01, 0b10]);
+        assert_eq!(netlist.to_text(), with_header(text));
+        let too_many_addresses = "component ram 0 0 north address-width:9";
+        assert!(Netlist::from_text(too_many_addresses, &types).is_err());
+        let not_ram = "component register 0 0 north address-width:2";
+        assert!(Netlist::from_text(not_ram, &types).is_err());
+    }
+
+    #[test]
+    fn newer_formats_are_refused() {
+        let types = ComponentType::enumerate();
+        let text = "component and 0 0 north\n";
+        // netlists from before the header was written, or typed by hand, are read all the same
+        assert!(Netlist::from_text(text, &types).is_ok());
+        let current = Netlist::from_text(&with_header(text), &types).unwrap();
+        assert_eq!(current.components.len(), 1);
+        let newer = format!("{} {}\n{}", HEADER, FORMAT_VERSION + 1, text);
+        assert!(Netlist::from_text(&newer, &types).is_err());
+        let garbled = format!("{} one\n{}", HEADER, text);
+        assert!(Netlist::from_text(&garbled, &types).is_err());
+    }
+
+    #[test]
+    fn divisors_and_delays_round_trip() {
+        let types = ComponentType::enumerate();
+        let text = "component clock-divider 0 0 north divisor:5 on holds:1100\n";
+        let netlist = Netlist::from_text(text, &types).unwrap();
+        assert_eq!(netlist.components[0].divisor, 5);
+        assert_eq!(netlist.to_text(), with_header(text));
+        let too_many = "component clock-divider 0 0 north divisor:17";
+        assert!(Netlist::from_text(too_many, &types).is_err());
+        let not_a_divider = "component dff 0 0 north divisor:3";
+        assert!(Netlist::from_text(not_a_divider, &types).is_err());
+
+        let text = "component delay 0 0 north delay:3 on holds:1110000000000000\n";
+        let netlist = Netlist::from_text(text, &types).unwrap();
+        assert_eq!(netlist.components[0].delay, 3);
+        assert_eq!(netlist.to_text(), with_header(text));
+        let too_long = "component delay 0 0 north delay:17";
+        assert!(Netlist::from_text(too_long, &types).is_err());
+        let not_a_delay = "component clock-divider 0 0 north delay:3";
+        assert!(Netlist::from_text(not_a_delay, &types).is_err());
+    }
+
+    #[test]
+    fn bubbles_only_go_where_the_ui_puts_them() {
+        let types = ComponentType::enumerate();
+        assert!(Netlist::from_text("component and 0 0 north inverted", &types).is_ok());
+        // nand already has a bubble on its output
+        assert!(Netlist::from_text("component nand 0 0 north inverted", &types).is_err());
+        // pin 1 of a not gate is its output
+        assert!(Netlist::from_text("component not 0 0 north inverted-input:0", &types).is_ok());
+        assert!(Netlist::from_text("component not 0 0 north inverted-input:1", &types).is_err());
+    }
+
+    #[test]
+    fn wire_styles_round_trip() {
+        let types = ComponentType::enumerate();
+        let text = "wire 0,0 0,4 4,4 color:d04040 dashed\nwire 1,0 1,-3 dashed\nwire 2,0 5,3\n";
+        let netlist = Netlist::from_text(text, &types).unwrap();
+        let dashed: Vec<_> = netlist.wires.iter().map(|wire| wire.style.dashed).collect();
+        assert_eq!(dashed, [true, true, false]);
+        assert_eq!(netlist.to_text(), with_header(text));
+        let bad_color = "wire 0,0 0,4 color:d040";
+        assert!(Netlist::from_text(bad_color, &types).is_err());
+        let points_after_style = "wire 0,0 0,4 dashed 4,4";
+        assert!(Netlist::from_text(points_after_style, &types).is_err());
+    }
+}
@@ -0,0 +1,747 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    canvas::{Coords, ItemId},
+    component::{ComponentInstance, PinType},
+};
+
+/// The most inputs a truth table can have, since each one doubles how many rows it has
+pub const MAX_TRUTH_TABLE_INPUTS: usize = 12;
+/// How many times settling lets clocked components take in their inputs, unless it's told
+/// otherwise. Each time can clock a flip-flop driven by another or move a delay line along by a
+/// step, but flip-flops clocking each other round in a loop can go on forever
+pub const MAX_SETTLE_PASSES: usize = 64;
+
+/// A pin of a component, by its index in the type's pin list
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PinRef {
+    pub component: ItemId,
+    pub pin: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimError {
+    /// The component's output feeds back into its own inputs through other components, so there's
+    /// no order to evaluate them in. Going through a clocked input doesn't count
+    Cycle(ItemId),
+    /// A truth table was asked for with this many inputs, more than `MAX_TRUTH_TABLE_INPUTS`
+    TooManyInputs(usize),
+    /// These clocked components, in order, were still changing when settling ran out of passes
+    Oscillating(Vec<ItemId>),
+}
+
+impl std::fmt::Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimError::Cycle(_) => f.write_str("the circuit has a feedback loop"),
+            SimError::TooManyInputs(n) => write!(
+                f,
+                "a truth table can have at most {} inputs, not {}",
+                MAX_TRUTH_TABLE_INPUTS, n
+            ),
+            SimError::Oscillating(ids) if ids.len() == 1 => {
+                f.write_str("a clocked component never settles")
+            },
+            SimError::Oscillating(ids) => {
+                write!(f, "{} clocked components never settle", ids.len())
+            },
+        }
+    }
+}
+
+/// Something a truth table reads the level of
+#[derive(Clone, Copy, Debug)]
+pub enum Probe {
+    /// Whether an LED is lit
+    Light(ItemId),
+    Pin(PinRef),
+}
+
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// One row per combination of inputs, with the levels of the inputs followed by those of the
+    /// outputs
+    pub rows: Vec<Vec<bool>>,
+}
+
+impl TruthTable {
+    /// A header of input and output names, then the rows as 0s and 1s
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let names: Vec<_> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .cloned()
+            .collect();
+        csv.push_str(&names.join(","));
+        csv.push('\n');
+        for row in self.rows.iter() {
+            let levels: Vec<_> = row
+                .iter()
+                .map(|level| if *level { "1" } else { "0" })
+                .collect();
+            csv.push_str(&levels.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Works out the level on every pin of every component. Pins are connected when wires join their
+/// coordinates, through any number of wires. Inputs nothing drives are low, and so are the outputs
+/// of disabled components. If several outputs drive one input, it's high if any of them are
+pub fn simulate<'a>(
+    components: impl IntoIterator<Item = (ItemId, &'a ComponentInstance)>,
+    wires: impl IntoIterator<Item = (Coords, Coords)>,
+) -> Result<HashMap<PinRef, bool>, SimError> {
+    let components: HashMap<_, _> = components.into_iter().collect();
+
+    // every point a wire ends on points towards another point in the same net, until the one
+    // that stands for the whole net
+    let mut parents = HashMap::new();
+    fn root(parents: &HashMap<Coords, Coords>, mut coords: Coords) -> Coords {
+        while let Some(parent) = parents.get(&coords) {
+            coords = *parent;
+        }
+        coords
+    }
+    for (a, b) in wires {
+        let (a, b) = (root(&parents, a), root(&parents, b));
+        if a != b {
+            parents.insert(a, b);
+        }
+    }
+
+    let mut nets = HashMap::new();
+    let mut drivers: HashMap<Coords, Vec<PinRef>> = HashMap::new();
+    for (id, instance) in components.iter() {
+        for (pin, coords) in instance.resolved_pins().enumerate() {
+            let net = root(&parents, coords);
+            let pin_ref = PinRef {
+                component: *id,
+                pin,
+            };
+            nets.insert(pin_ref, net);
+            let drives = instance.enabled
+                && instance.ty().function.is_some()
+                && instance.pin_type(pin) == PinType::Output;
+            if drives {
+                drivers.entry(net).or_default().push(pin_ref);
+            }
+        }
+    }
+
+    let mut simulation = Simulation {
+        components: &components,
+        nets,
+        drivers,
+        levels: HashMap::new(),
+        evaluating: HashSet::new(),
+        evaluated: HashSet::new(),
+    };
+    for id in components.keys() {
+        simulation.evaluate(*id)?;
+    }
+    // clocked inputs aren't needed for any outputs, but settling takes them in
+    for (id, instance) in components.iter() {
+        let pins = instance.ty().pin_count();
+        for pin in (0..pins).filter(|pin| instance.is_clocked_input(*pin)) {
+            simulation.evaluate_input(PinRef {
+                component: *id,
+                pin,
+            })?;
+        }
+    }
+    Ok(simulation.levels)
+}
+
+/// Lets the clocked components take in the levels on their inputs, and simulates again with what
+/// they now hold, until they stop changing. They all take in the same levels at once, so a chain
+/// of flip-flops sharing a clock shifts along by one. Fails with the ones that are still changing
+/// after `max_passes` passes, usually `MAX_SETTLE_PASSES`
+pub fn settle(
+    components: &mut HashMap<ItemId, ComponentInstance>,
+    wires: &[(Coords, Coords)],
+    max_passes: usize,
+) -> Result<(), SimError> {
+    let mut changing = Vec::new();
+    for _ in 0..max_passes {
+        let levels = simulate(
+            components.iter().map(|(id, instance)| (*id, instance)),
+            wires.iter().copied(),
+        )?;
+        changing.clear();
+        for (id, instance) in components.iter_mut() {
+            if instance.ty().is_clocked() {
+                let inputs = inputs(&levels, *id, instance);
+                if instance.latch(&inputs) {
+                    changing.push(*id);
+                }
+            }
+        }
+        if changing.is_empty() {
+            return Ok(());
+        }
+    }
+    changing.sort();
+    Err(SimError::Oscillating(changing))
+}
+
+/// Simulates the circuit with the given switches set to every combination in turn, the first
+/// changing slowest, and reads the outputs each time. Fails if there are more than
+/// `MAX_TRUTH_TABLE_INPUTS` inputs, or the circuit can't be simulated
+pub fn truth_table(
+    mut components: HashMap<ItemId, ComponentInstance>,
+    wires: &[(Coords, Coords)],
+    inputs: &[(String, ItemId)],
+    outputs: &[(String, Probe)],
+) -> Result<TruthTable, SimError> {
+    let n = inputs.len();
+    if n > MAX_TRUTH_TABLE_INPUTS {
+        return Err(SimError::TooManyInputs(n));
+    }
+    let mut rows = Vec::new();
+    for combination in 0..1usize << n {
+        let mut row: Vec<_> = (0..n)
+            .map(|i| (combination >> (n - 1 - i)) & 1 == 1)
+            .collect();
+        for ((_, id), level) in inputs.iter().zip(row.iter()) {
+            components.get_mut(id).unwrap().value = *level;
+        }
+        let levels = simulate(
+            components.iter().map(|(id, instance)| (*id, instance)),
+            wires.iter().copied(),
+        )?;
+        row.extend(outputs.iter().map(|(_, probe)| match *probe {
+            Probe::Light(id) => output(&levels, id, &components[&id]),
+            Probe::Pin(pin) => levels.get(&pin).copied().unwrap_or(false),
+        }));
+        rows.push(row);
+    }
+    Ok(TruthTable {
+        inputs: inputs.iter().map(|(name, _)| name.clone()).collect(),
+        outputs: outputs.iter().map(|(name, _)| name.clone()).collect(),
+        rows,
+    })
+}
+
+/// What a component's inputs take in, in pin list order, after any inversion bubbles. Inputs
+/// without a level yet are taken as low
+pub fn inputs(
+    levels: &HashMap<PinRef, bool>,
+    id: ItemId,
+    instance: &ComponentInstance,
+) -> Vec<bool> {
+    (0..instance.ty().pin_count())
+        .filter(|pin| instance.pin_type(*pin) == PinType::Input)
+        .map(|pin| {
+            let level = levels
+                .get(&PinRef { component: id, pin })
+                .copied()
+                .unwrap_or(false);
+            // the bubble is part of the component, so the pin itself carries the level before it
+            level != instance.inverted_inputs[pin]
+        })
+        .collect()
+}
+
+/// What each of a component's outputs gives once the levels on its inputs are known, in pin list
+/// order and including any inversion bubbles. Most give the same, but registers and RAM give each
+/// output its own bit of a word
+pub fn outputs(
+    levels: &HashMap<PinRef, bool>,
+    id: ItemId,
+    instance: &ComponentInstance,
+) -> Vec<bool> {
+    let count = instance.ty().output_count();
+    match instance.word_outputs(&inputs(levels, id, instance)) {
+        Some(_) if !instance.enabled => vec![false; count],
+        Some(word) => word
+            .into_iter()
+            .map(|bit| bit != instance.output_inverted)
+            .collect(),
+        None => vec![output(levels, id, instance); count],
+    }
+}
+
+/// What a component's function gives once the levels on its inputs are known, including any
+/// inversion bubbles. For LEDs, this is whether they're lit
+pub fn output(levels: &HashMap<PinRef, bool>, id: ItemId, instance: &ComponentInstance) -> bool {
+    let inputs = inputs(levels, id, instance);
+    match instance.ty().function {
+        Some(function) if instance.enabled => {
+            function.evaluate(&inputs, instance.value) != instance.output_inverted
+        },
+        _ => false,
+    }
+}
+
+struct Simulation<'a, 'b> {
+    components: &'b HashMap<ItemId, &'a ComponentInstance>,
+    nets: HashMap<PinRef, Coords>,
+    drivers: HashMap<Coords, Vec<PinRef>>,
+    levels: HashMap<PinRef, bool>,
+    /// The components whose inputs are being worked out, to catch evaluating one inside itself
+    evaluating: HashSet<ItemId>,
+    evaluated: HashSet<ItemId>,
+}
+
+impl Simulation<'_, '_> {
+    /// Works out the levels on a component's pins, after those on the outputs driving it. Clocked
+    /// inputs are left for later, since the outputs don't depend on them
+    fn evaluate(&mut self, id: ItemId) -> Result<(), SimError> {
+        if self.evaluated.contains(&id) {
+            return Ok(());
+        }
+        if !self.evaluating.insert(id) {
+            return Err(SimError::Cycle(id));
+        }
+        let instance = self.components[&id];
+        let pins = instance.ty().pin_count();
+        for pin in (0..pins).filter(|pin| {
+            instance.pin_type(*pin) == PinType::Input && !instance.is_clocked_input(*pin)
+        }) {
+            self.evaluate_input(PinRef { component: id, pin })?;
+        }
+        let outputs = outputs(&self.levels, id, instance);
+        let output_pins = (0..pins).filter(|pin| instance.pin_type(*pin) == PinType::Output);
+        for (pin, output) in output_pins.zip(outputs) {
+            self.levels.insert(PinRef { component: id, pin }, output);
+        }
+        self.evaluating.remove(&id);
+        self.evaluated.insert(id);
+        Ok(())
+    }
+
+    /// Works out the level on an input from the outputs driving it
+    fn evaluate_input(&mut self, pin_ref: PinRef) -> Result<(), SimError> {
+        let drivers = self
+            .drivers
+            .get(&self.nets[&pin_ref])
+            .cloned()
+            .unwrap_or_default();
+        let mut level = false;
+        for driver in drivers {
+            self.evaluate(driver.component)?;
+            level |= self.levels[&driver];
+        }
+        self.levels.insert(pin_ref, level);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{ComponentType, Orientation};
+    use druid::im;
+
+    fn instance(name: &str, x: isize, y: isize) -> ComponentInstance {
+        let ty = ComponentType::enumerate()
+            .into_iter()
+            .find(|ty| ty.name == name)
+            .unwrap();
+        ComponentInstance::new(Coords::new(x, y), ty, Orientation::North)
+    }
+
+    fn pin(instance: &ComponentInstance, name: &str) -> Coords {
+        let pin = instance.pin_named(name).unwrap();
+        instance.resolved_pins().nth(pin).unwrap()
+    }
+
+    #[test]
+    fn and_gate_of_two_switches() {
+        let gate = instance("and", 10, 10);
+        let y = gate.pin_named("Y").unwrap();
+        for &(a, b) in &[(false, false), (false, true), (true, false), (true, true)] {
+            let mut switch_a = instance("switch", 0, 0);
+            let mut switch_b = instance("switch", 0, 4);
+            switch_a.value = a;
+            switch_b.value = b;
+            let wires = vec![
+                (pin(&switch_a, "Y"), pin(&gate, "A")),
+                (pin(&switch_b, "Y"), pin(&gate, "B")),
+            ];
+            let gate_id = ItemId::next();
+            let components = vec![
+                (ItemId::next(), &switch_a),
+                (ItemId::next(), &switch_b),
+                (gate_id, &gate),
+            ];
+            let levels = simulate(components, wires).unwrap();
+            let output = levels[&PinRef {
+                component: gate_id,
+                pin: y,
+            }];
+            assert_eq!(output, a && b, "A = {}, B = {}", a, b);
+        }
+    }
+
+    #[test]
+    fn unconnected_inputs_are_low() {
+        let gate = instance("nand", 0, 0);
+        let id = ItemId::next();
+        let levels = simulate(vec![(id, &gate)], Vec::new()).unwrap();
+        let level = |name| {
+            levels[&PinRef {
+                component: id,
+                pin: gate.pin_named(name).unwrap(),
+            }]
+        };
+        assert!(!level("A"));
+        assert!(!level("B"));
+        assert!(level("Y"));
+    }
+
+    #[test]
+    fn feedback_loop_is_a_cycle() {
+        let first = instance("not", 0, 0);
+        let second = instance("not", 4, 0);
+        let wires = vec![
+            (pin(&first, "Y"), pin(&second, "A")),
+            (pin(&second, "Y"), pin(&first, "A")),
+        ];
+        let components = vec![(ItemId::next(), &first), (ItemId::next(), &second)];
+        assert!(matches!(
+            simulate(components, wires),
+            Err(SimError::Cycle(_))
+        ));
+    }
+
+    #[test]
+    fn parity_counts_the_high_inputs() {
+        for (name, even) in [("parity-even", true), ("parity-odd", false)] {
+            let mut gate = instance(name, 20, 0);
+            assert!(gate.set_input_count(4));
+            let y = gate.pin_named("Y").unwrap();
+            for pattern in [0b0000u32, 0b0001, 0b0110, 0b1011, 0b1111, 0b1000] {
+                let inputs = ["A", "B", "C", "D"];
+                let switches: Vec<_> = (0..inputs.len())
+                    .map(|i| {
+                        let mut switch = instance("switch", 0, 4 * i as isize);
+                        switch.value = pattern >> i & 1 == 1;
+                        switch
+                    })
+                    .collect();
+                let wires: Vec<_> = switches
+                    .iter()
+                    .zip(inputs)
+                    .map(|(switch, input)| (pin(switch, "Y"), pin(&gate, input)))
+                    .collect();
+                let id = ItemId::next();
+                let components = switches
+                    .iter()
+                    .map(|switch| (ItemId::next(), switch))
+                    .chain(std::iter::once((id, &gate)));
+                let levels = simulate(components, wires).unwrap();
+                let output = levels[&PinRef {
+                    component: id,
+                    pin: y,
+                }];
+                let high = pattern.count_ones() + output as u32;
+                assert_eq!(
+                    high % 2,
+                    if even { 0 } else { 1 },
+                    "{} of {:04b}",
+                    name,
+                    pattern
+                );
+            }
+        }
+    }
+
+    /// A clocked component with a switch wired to each of the given inputs
+    struct Clocked {
+        components: HashMap<ItemId, ComponentInstance>,
+        wires: Vec<(Coords, Coords)>,
+        part: ItemId,
+        switches: HashMap<&'static str, ItemId>,
+    }
+
+    impl Clocked {
+        fn new(name: &str, inputs: &[&'static str]) -> Self {
+            let part = instance(name, 20, 20);
+            let mut clocked = Clocked {
+                components: HashMap::new(),
+                wires: Vec::new(),
+                part: ItemId::next(),
+                switches: HashMap::new(),
+            };
+            for (i, input) in inputs.iter().enumerate() {
+                let switch = instance("switch", 0, 4 * i as isize);
+                clocked.wires.push((pin(&switch, "Y"), pin(&part, input)));
+                let id = ItemId::next();
+                clocked.components.insert(id, switch);
+                clocked.switches.insert(input, id);
+            }
+            clocked.components.insert(clocked.part, part);
+            clocked
+        }
+
+        /// Switches an input and lets the circuit settle
+        fn set(&mut self, input: &str, level: bool) {
+            self.components
+                .get_mut(&self.switches[input])
+                .unwrap()
+                .value = level;
+            settle(&mut self.components, &self.wires, MAX_SETTLE_PASSES).unwrap();
+        }
+
+        fn held(&self) -> bool {
+            self.components[&self.part].value
+        }
+
+        /// The levels on the named pins of the part being tested
+        fn levels(&self, pins: &[&str]) -> Vec<bool> {
+            let part = &self.components[&self.part];
+            let levels = simulate(
+                self.components.iter().map(|(id, instance)| (*id, instance)),
+                self.wires.iter().copied(),
+            )
+            .unwrap();
+            pins.iter()
+                .map(|name| {
+                    levels[&PinRef {
+                        component: self.part,
+                        pin: part.pin_named(name).unwrap(),
+                    }]
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn flip_flop_takes_d_when_clocked() {
+        let mut dff = Clocked::new("dff", &["D", "CLK"]);
+        dff.set("D", true);
+        assert!(!dff.held(), "took D without a clock edge");
+        dff.set("CLK", true);
+        assert!(dff.held());
+        dff.set("D", false);
+        assert!(dff.held(), "took D while the clock stayed high");
+        dff.set("CLK", false);
+        assert!(dff.held(), "took D as the clock fell");
+        dff.set("CLK", true);
+        assert!(!dff.held());
+    }
+
+    #[test]
+    fn async_reset_overrides_a_clock_edge() {
+        let mut dff = Clocked::new("dff-sr", &["D", "CLK", "S", "R"]);
+        dff.components.get_mut(&dff.part).unwrap().value = true;
+        // resetting shows on Q straight away, before anything is clocked
+        let reset = dff.switches["R"];
+        dff.components.get_mut(&reset).unwrap().value = true;
+        let levels = simulate(
+            dff.components.iter().map(|(id, instance)| (*id, instance)),
+            dff.wires.iter().copied(),
+        )
+        .unwrap();
+        let q = PinRef {
+            component: dff.part,
+            pin: dff.components[&dff.part].pin_named("Q").unwrap(),
+        };
+        assert!(!levels[&q]);
+        // and it wins over D being clocked in at the same time
+        dff.set("D", true);
+        dff.set("CLK", true);
+        assert!(!dff.held());
+        // once it's let go, the flip-flop waits for the next edge
+        dff.set("R", false);
+        assert!(!dff.held());
+        dff.set("S", true);
+        assert!(dff.held());
+    }
+
+    #[test]
+    fn feedback_through_a_flip_flop_isnt_a_cycle() {
+        // a flip-flop that flips every time it's clocked
+        let mut dff = Clocked::new("dff", &["CLK"]);
+        let not = instance("not", 30, 0);
+        let part = &dff.components[&dff.part];
+        dff.wires.push((pin(part, "Q"), pin(&not, "A")));
+        dff.wires.push((pin(&not, "Y"), pin(part, "D")));
+        dff.components.insert(ItemId::next(), not);
+        for expected in [true, false, true] {
+            dff.set("CLK", true);
+            dff.set("CLK", false);
+            assert_eq!(dff.held(), expected);
+        }
+    }
+
+    #[test]
+    fn flip_flops_clocking_each_other_never_settle() {
+        // each flips when the other's clock rises, and each flip raises the other's clock
+        let mut first = instance("dff", 0, 0);
+        let mut second = instance("dff", 10, 0);
+        let xor = instance("xor", 20, 0);
+        let xnor = instance("xnor", 30, 0);
+        for flip_flop in [&mut first, &mut second] {
+            let d = flip_flop.pin_named("D").unwrap();
+            flip_flop.inverted_inputs.set(d, true);
+        }
+        let wires = vec![
+            (pin(&first, "Q"), pin(&first, "D")),
+            (pin(&second, "Q"), pin(&second, "D")),
+            (pin(&first, "Q"), pin(&xor, "A")),
+            (pin(&second, "Q"), pin(&xor, "B")),
+            (pin(&first, "Q"), pin(&xnor, "A")),
+            (pin(&second, "Q"), pin(&xnor, "B")),
+            (pin(&xor, "Y"), pin(&first, "CLK")),
+            (pin(&xnor, "Y"), pin(&second, "CLK")),
+        ];
+        let ids: Vec<_> = (0..4).map(|_| ItemId::next()).collect();
+        let mut components: HashMap<_, _> = ids
+            .iter()
+            .copied()
+            .zip(vec![first, second, xor, xnor])
+            .collect();
+        // only the flip-flops are to blame
+        match settle(&mut components, &wires, MAX_SETTLE_PASSES) {
+            Err(SimError::Oscillating(changing)) => {
+                assert!(!changing.is_empty());
+                assert!(changing.iter().all(|id| ids[..2].contains(id)));
+            },
+            other => panic!("settled to {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clock_divider_flips_every_divisor_rising_edges() {
+        for divisor in [1, 2, 3, 5] {
+            let mut divider = Clocked::new("clock-divider", &["CLK"]);
+            let part = divider.components.get_mut(&divider.part).unwrap();
+            part.divisor = divisor;
+            // the rising edges after which Q flipped
+            let mut flips = Vec::new();
+            for edge in 1..=4 * divisor {
+                let before = divider.held();
+                divider.set("CLK", true);
+                divider.set("CLK", false);
+                if divider.held() != before {
+                    flips.push(edge);
+                }
+            }
+            let expected: Vec<_> = (1..=4).map(|n| n * divisor).collect();
+            assert_eq!(flips, expected, "dividing by {}", divisor);
+            assert_eq!(divider.levels(&["Q"]), [false], "dividing by {}", divisor);
+        }
+    }
+
+    #[test]
+    fn delay_line_lags_a_by_its_delay() {
+        for delay in [1, 3, 16] {
+            let mut line = Clocked::new("delay", &["A"]);
+            line.components.get_mut(&line.part).unwrap().delay = delay;
+            line.components.get_mut(&line.switches["A"]).unwrap().value = true;
+            // a pass of settling at a time, whether Y has caught up with A after it
+            let caught_up: Vec<_> = (1..=delay + 1)
+                .map(|_| {
+                    let levels = simulate(
+                        line.components.iter().map(|(id, instance)| (*id, instance)),
+                        line.wires.iter().copied(),
+                    )
+                    .unwrap();
+                    let inputs = inputs(&levels, line.part, &line.components[&line.part]);
+                    line.components.get_mut(&line.part).unwrap().latch(&inputs);
+                    line.levels(&["Y"])[0]
+                })
+                .collect();
+            let expected: Vec<_> = (1..=delay + 1).map(|pass| pass >= delay).collect();
+            assert_eq!(caught_up, expected, "delaying by {}", delay);
+            // settling goes on until the line is full of A, as long as it's allowed to
+            line.set("A", false);
+            assert_eq!(line.levels(&["Y"]), [false], "delaying by {}", delay);
+            line.components.get_mut(&line.switches["A"]).unwrap().value = true;
+            let cut_short = settle(&mut line.components, &line.wires, delay);
+            assert_eq!(cut_short, Err(SimError::Oscillating(vec![line.part])));
+        }
+    }
+
+    #[test]
+    fn register_holds_while_not_enabled() {
+        let mut register = Clocked::new("register", &["D0", "D1", "D2", "D3", "EN", "CLK"]);
+        let held = |register: &Clocked| register.components[&register.part].memory.clone();
+        register.set("D0", true);
+        register.set("D2", true);
+        register.set("EN", true);
+        register.set("CLK", true);
+        assert_eq!(held(&register), im::vector![true, false, true, false]);
+        // each output gives its own bit
+        assert_eq!(
+            register.levels(&["Q0", "Q1", "Q2", "Q3"]),
+            [true, false, true, false]
+        );
+
+        register.set("CLK", false);
+        register.set("EN", false);
+        register.set("D0", false);
+        register.set("D1", true);
+        register.set("CLK", true);
+        assert_eq!(held(&register), im::vector![true, false, true, false]);
+    }
+
+    const RAM_INPUTS: [&str; 10] = ["A0", "A1", "A2", "A3", "D0", "D1", "D2", "D3", "WE", "CLK"];
+    const RAM_OUTPUTS: [&str; 4] = ["Q0", "Q1", "Q2", "Q3"];
+
+    #[test]
+    fn ram_writes_on_the_clock_edge_and_reads_straight_away() {
+        let mut ram = Clocked::new("ram", &RAM_INPUTS);
+        ram.set("A1", true);
+        ram.set("D0", true);
+        ram.set("D3", true);
+        ram.set("WE", true);
+        assert_eq!(
+            ram.levels(&RAM_OUTPUTS),
+            [false; 4],
+            "wrote without a clock edge"
+        );
+        ram.set("CLK", true);
+        assert_eq!(ram.levels(&RAM_OUTPUTS), [true, false, false, true]);
+        assert_eq!(ram.components[&ram.part].words()[2], 0b1001);
+
+        ram.set("CLK", false);
+        ram.set("WE", false);
+        ram.set("A1", false);
+        assert_eq!(
+            ram.levels(&RAM_OUTPUTS),
+            [false; 4],
+            "didn't follow the address"
+        );
+        ram.set("A1", true);
+        ram.set("D0", false);
+        ram.set("CLK", true);
+        assert_eq!(
+            ram.levels(&RAM_OUTPUTS),
+            [true, false, false, true],
+            "wrote while WE was low"
+        );
+    }
+
+    #[test]
+    fn clocked_reads_wait_for_the_clock() {
+        let mut ram = Clocked::new("ram-sync", &RAM_INPUTS);
+        ram.set("A0", true);
+        ram.set("D1", true);
+        ram.set("WE", true);
+        ram.set("CLK", true);
+        // the read on that edge was of what was there before the write
+        assert_eq!(ram.levels(&RAM_OUTPUTS), [false; 4]);
+        ram.set("CLK", false);
+        ram.set("WE", false);
+        ram.set("CLK", true);
+        assert_eq!(ram.levels(&RAM_OUTPUTS), [false, true, false, false]);
+
+        ram.set("A0", false);
+        assert_eq!(
+            ram.levels(&RAM_OUTPUTS),
+            [false, true, false, false],
+            "read without a clock edge"
+        );
+        ram.set("CLK", false);
+        ram.set("CLK", true);
+        assert_eq!(ram.levels(&RAM_OUTPUTS), [false; 4]);
+    }
+}
@@ -0,0 +1,56 @@
+use druid::{Color, Env, Key};
+
+/// Outline of the selected component that keyboard commands act on
+pub const SELECTION_COLOR: Key<Color> = Key::new("logicism.theme.selection-color");
+/// Outline of the other components in a multiple selection
+pub const SECONDARY_SELECTION_COLOR: Key<Color> =
+    Key::new("logicism.theme.secondary-selection-color");
+/// Ring around the component with keyboard focus, drawn whether or not it's selected
+pub const FOCUS_RING_COLOR: Key<Color> = Key::new("logicism.theme.focus-ring-color");
+
+/// Wires without a colour of their own
+pub const WIRE_COLOR: Key<Color> = Key::new("logicism.theme.wire-color");
+
+/// The pin under the mouse
+pub const HOVERED_PIN_COLOR: Key<Color> = Key::new("logicism.theme.hovered-pin-color");
+
+/// Ring around the pin a wire being drawn will connect to
+pub const CONNECT_TARGET_COLOR: Key<Color> = Key::new("logicism.theme.connect-target-color");
+/// Ring around a pin a wire being drawn would be refused at, like a second output
+pub const REFUSED_TARGET_COLOR: Key<Color> = Key::new("logicism.theme.refused-target-color");
+
+/// Inside of a lit LED, and of a switch that's switched on
+pub const HIGH_LEVEL_COLOR: Key<Color> = Key::new("logicism.theme.high-level-color");
+/// Inside of an unlit LED, and of a switch that's switched off
+pub const LOW_LEVEL_COLOR: Key<Color> = Key::new("logicism.theme.low-level-color");
+
+/// How far the lead drawn out of each pin sticks out from the component, in pixels. Should stay
+/// under the component's paint insets of 8
+pub const PIN_STUB_LENGTH: Key<f64> = Key::new("logicism.theme.pin-stub-length");
+
+/// Whether components visibly turn when rotated rather than snapping round. Turn it off for
+/// reduced motion
+pub const ANIMATE_ROTATION: Key<bool> = Key::new("logicism.theme.animate-rotation");
+
+/// Whether pin leads carry an arrow pointing into the component for inputs and out of it for
+/// outputs. Helps when learning which way signals flow
+pub const SHOW_PIN_DIRECTIONS: Key<bool> = Key::new("logicism.theme.show-pin-directions");
+
+/// Whether the pin markers are drawn. The leads out of the body are part of the symbol and stay
+pub const SHOW_PINS: Key<bool> = Key::new("logicism.theme.show-pins");
+
+pub fn configure_env(env: &mut Env) {
+    env.set(SELECTION_COLOR, Color::AQUA);
+    env.set(SECONDARY_SELECTION_COLOR, Color::rgb8(0x00, 0x80, 0x80));
+    env.set(FOCUS_RING_COLOR, Color::rgb8(0xff, 0xd7, 0x00));
+    env.set(WIRE_COLOR, Color::GREEN);
+    env.set(HOVERED_PIN_COLOR, Color::rgb8(0x7f, 0xff, 0x00));
+    env.set(CONNECT_TARGET_COLOR, Color::rgb8(0xff, 0xa5, 0x00));
+    env.set(REFUSED_TARGET_COLOR, Color::rgb8(0xff, 0x30, 0x30));
+    env.set(HIGH_LEVEL_COLOR, Color::rgb8(0x30, 0xe0, 0x30));
+    env.set(LOW_LEVEL_COLOR, Color::rgb8(0x30, 0x30, 0x30));
+    env.set(PIN_STUB_LENGTH, 4.0);
+    env.set(ANIMATE_ROTATION, true);
+    env.set(SHOW_PIN_DIRECTIONS, false);
+    env.set(SHOW_PINS, true);
+}
@@ -1,6 +1,44 @@
-use druid::{im, Color, Data, PaintCtx, Point, Rect, RenderContext, Size, Widget};
+use druid::{
+    im, kurbo::Line, piet::StrokeStyle, Affine, Color, Data, Env, Event, PaintCtx, Point, Rect,
+    RenderContext, Widget,
+};
 
-use crate::canvas::Coords;
+use crate::{
+    canvas::{Coords, ItemId, BEGIN_DRAG, DESELECT_ALL},
+    theme::{SELECTION_COLOR, WIRE_COLOR},
+};
+
+/// The colours wires can be given, in the order they're cycled through
+const WIRE_COLORS: [Color; 5] = [
+    Color::rgb8(0xd0, 0x40, 0x40),
+    Color::rgb8(0xe0, 0xa0, 0x20),
+    Color::rgb8(0x40, 0xa0, 0x40),
+    Color::rgb8(0x40, 0x80, 0xe0),
+    Color::rgb8(0xa0, 0x60, 0xd0),
+];
+
+/// Per-wire overrides of how the wire is drawn
+#[derive(Clone, Data, Default)]
+pub struct WireStyle {
+    /// Falls back to the theme's wire colour when unset
+    pub color: Option<Color>,
+    pub dashed: bool,
+}
+
+impl WireStyle {
+    /// Gives the wire the next of the wire colours, going back to the theme's colour after the
+    /// last one
+    pub fn cycle_color(&mut self) {
+        let next = match self.color {
+            None => 0,
+            Some(ref color) => WIRE_COLORS
+                .iter()
+                .position(|c| c.as_rgba_u32() == color.as_rgba_u32())
+                .map_or(WIRE_COLORS.len(), |i| i + 1),
+        };
+        self.color = WIRE_COLORS.get(next).cloned();
+    }
+}
 
 #[derive(Clone, Data)]
 pub struct WireSegment {
@@ -9,40 +47,180 @@ pub struct WireSegment {
 }
 
 impl WireSegment {
+    /// A segment between two points in line horizontally, vertically or at 45 degrees
     pub fn new(start: Coords, end: Coords) -> Option<Self> {
-        if start.x != end.x && start.y != end.y {
+        let (dx, dy) = (start.x.abs_diff(end.x), start.y.abs_diff(end.y));
+        if dx != 0 && dy != 0 && dx != dy {
             None
         } else {
             Some(WireSegment { start, end })
         }
     }
 
+    pub fn is_diagonal(&self) -> bool {
+        self.start.x != self.end.x && self.start.y != self.end.y
+    }
+
+    pub fn start(&self) -> Coords {
+        self.start
+    }
+
+    pub fn end(&self) -> Coords {
+        self.end
+    }
+
+    /// Whether the point lies on the segment, including its ends
+    pub fn contains(&self, coords: Coords) -> bool {
+        let (x0, x1) = (self.start.x.min(self.end.x), self.start.x.max(self.end.x));
+        let (y0, y1) = (self.start.y.min(self.end.y), self.start.y.max(self.end.y));
+        let in_box = (x0..=x1).contains(&coords.x) && (y0..=y1).contains(&coords.y);
+        if self.is_diagonal() {
+            // on the diagonal, a point is as far across from the start as it is down or up
+            in_box && coords.x.abs_diff(self.start.x) == coords.y.abs_diff(self.start.y)
+        } else {
+            in_box
+        }
+    }
+
     pub fn bounding_rect(&self) -> Rect {
-        let start = self.start.to_widget_space();
-        let end = self.end.to_widget_space();
+        let start = self.start.to_canvas_space();
+        let end = self.end.to_canvas_space();
         Rect::from_points(start, end).inflate(2.0, 2.0)
     }
 
-    pub fn paint(&self, ctx: &mut PaintCtx) {
-        let start = self.start.to_widget_space();
-        let end = self.end.to_widget_space();
-        let rect = Rect::from_points(start, end)
-            .with_origin(Point::new(10.0, 10.0))
-            .inflate(1.0, 1.0);
-        ctx.fill(rect, &Color::GREEN);
-        ctx.fill(
-            Rect::from_origin_size(Point::ORIGIN, Size::new(2.0, 2.0)),
-            &Color::RED,
-        );
+    /// Paints the segment in canvas space
+    pub fn paint(&self, ctx: &mut PaintCtx, style: &WireStyle, env: &Env) {
+        let start = self.start.to_canvas_space();
+        let end = self.end.to_canvas_space();
+        let color = style.color.clone().unwrap_or_else(|| env.get(WIRE_COLOR));
+        if style.dashed {
+            let dashes = StrokeStyle::new().dash(vec![4.0, 4.0], 0.0);
+            ctx.stroke_styled(Line::new(start, end), &color, 2.0, &dashes);
+        } else if self.is_diagonal() {
+            ctx.stroke(Line::new(start, end), &color, 2.0);
+        } else {
+            let rect = Rect::from_points(start, end).inflate(1.0, 1.0);
+            ctx.fill(rect, &color);
+        }
     }
 }
 
+/// What one end of a wire is attached to
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub enum WireEnd {
+    /// A component's pin, by its index in the type's pin list. The end follows the pin when the
+    /// component is moved, rotated or mirrored
+    Pin(ItemId, usize),
+    Free(Coords),
+}
+
 #[derive(Clone, Data)]
 pub struct WireState {
     pub segments: im::Vector<WireSegment>,
+    pub start: WireEnd,
+    pub end: WireEnd,
+    pub style: WireStyle,
+    /// Selected wires move along with selected components
+    pub selected: bool,
+    /// Where the mouse was when the wire started being dragged, and where the wire started
+    dragging: Option<(Point, Coords)>,
 }
 
 impl WireState {
+    /// A wire along the given segments, with both ends free. `segments` must not be empty
+    pub fn new(segments: im::Vector<WireSegment>) -> Self {
+        let start = segments.front().unwrap().start;
+        let end = segments.back().unwrap().end;
+        WireState {
+            segments,
+            start: WireEnd::Free(start),
+            end: WireEnd::Free(end),
+            style: WireStyle::default(),
+            selected: false,
+            dragging: None,
+        }
+    }
+
+    /// Routes a wire between two points with a single bend. It goes horizontally first unless that
+    /// runs through one of the obstacles, in canvas space, and going vertically first doesn't
+    pub fn routed(start: Coords, end: Coords, obstacles: &[Rect]) -> Option<Self> {
+        if start == end {
+            return None;
+        }
+        let route = |corner: Coords| -> im::Vector<WireSegment> {
+            [(start, corner), (corner, end)]
+                .iter()
+                .filter(|(a, b)| a != b)
+                .filter_map(|&(a, b)| WireSegment::new(a, b))
+                .collect()
+        };
+        // pins sit on the edges of their components, so only running through the inside counts
+        let blocked = |segments: &im::Vector<WireSegment>| {
+            segments.iter().any(|segment| {
+                let rect = segment.bounding_rect();
+                obstacles
+                    .iter()
+                    .any(|obstacle| rect.intersect(obstacle.inset(-4.0)).area() > 0.0)
+            })
+        };
+        let horizontal_first = route(Coords::new(end.x, start.y));
+        let segments = if blocked(&horizontal_first) {
+            let vertical_first = route(Coords::new(start.x, end.y));
+            if blocked(&vertical_first) {
+                horizontal_first
+            } else {
+                vertical_first
+            }
+        } else {
+            horizontal_first
+        };
+        Some(WireState::new(segments))
+    }
+
+    /// Routes the wire again between new end points, keeping the old route if they've been brought
+    /// together
+    pub fn reroute(&mut self, start: Coords, end: Coords, obstacles: &[Rect]) {
+        if let Some(routed) = WireState::routed(start, end, obstacles) {
+            self.segments = routed.segments;
+        }
+    }
+
+    /// Moves the whole wire so that it starts at `start`. Ends attached to pins stay attached, so
+    /// they're pulled back to their pins when the wire is next rerouted
+    fn move_to(&mut self, start: Coords) {
+        let old_start = self.segments.front().unwrap().start;
+        let (dx, dy) = (start.x - old_start.x, start.y - old_start.y);
+        let shift = |c: Coords| Coords::new(c.x + dx, c.y + dy);
+        self.segments = self
+            .segments
+            .iter()
+            .map(|segment| WireSegment {
+                start: shift(segment.start),
+                end: shift(segment.end),
+            })
+            .collect();
+        for end in [&mut self.start, &mut self.end] {
+            if let WireEnd::Free(coords) = end {
+                *coords = shift(*coords);
+            }
+        }
+    }
+
+    /// Whether a point in canvas space is on one of the segments. The ends on pins don't count,
+    /// so that clicks there go to the pin
+    fn hit(&self, pos: Point) -> bool {
+        let coords = Coords::from_canvas_space(pos);
+        let on_pin = |end: WireEnd, at: Coords| matches!(end, WireEnd::Pin(..)) && at == coords;
+        if on_pin(self.start, self.segments.front().unwrap().start)
+            || on_pin(self.end, self.segments.back().unwrap().end)
+        {
+            return false;
+        }
+        self.segments
+            .iter()
+            .any(|segment| segment.bounding_rect().contains(pos))
+    }
+
     pub fn bounding_rect(&self) -> Rect {
         self.segments
             .iter()
@@ -50,18 +228,60 @@ impl WireState {
             .reduce(|a, b| a.union(b))
             .unwrap()
     }
+
+    /// Lets go of the wire if it's being dragged, for when a drag is cut short
+    pub fn end_drag(&mut self) {
+        self.dragging = None;
+    }
 }
 
-pub struct Wire(pub usize);
+pub struct Wire;
 
 impl Widget<WireState> for Wire {
     fn event(
         &mut self,
-        _ctx: &mut druid::EventCtx,
-        _event: &druid::Event,
-        _data: &mut WireState,
+        ctx: &mut druid::EventCtx,
+        event: &druid::Event,
+        data: &mut WireState,
         _env: &druid::Env,
     ) {
+        match event {
+            // the canvas is at the window's origin, so window positions are in canvas space
+            Event::MouseDown(ev) if data.hit(ev.window_pos) => {
+                if !data.selected {
+                    data.selected = true;
+                    if !ev.mods.ctrl() {
+                        ctx.submit_command(DESELECT_ALL.with(ctx.widget_id()));
+                    }
+                }
+                ctx.submit_command(BEGIN_DRAG.with(ev.window_pos));
+                ctx.set_handled();
+            },
+            Event::MouseUp(_) => {
+                data.dragging = None;
+                ctx.set_active(false);
+            },
+            Event::MouseMove(ev) => {
+                if let Some((mouse_start, wire_start)) = data.dragging {
+                    let start = wire_start.dragged(mouse_start, ev.window_pos);
+                    if start != data.segments.front().unwrap().start {
+                        data.move_to(start);
+                    }
+                }
+            },
+            Event::Command(c)
+                if c.is(DESELECT_ALL) && c.get(DESELECT_ALL) != Some(&ctx.widget_id()) =>
+            {
+                data.selected = false;
+                ctx.set_active(false);
+            },
+            Event::Command(c) if c.is(BEGIN_DRAG) && data.selected => {
+                let window_pos = c.get(BEGIN_DRAG).unwrap();
+                data.dragging = Some((*window_pos, data.segments.front().unwrap().start));
+                ctx.set_active(true);
+            },
+            _ => {},
+        }
     }
 
     fn lifecycle(
@@ -75,11 +295,14 @@ impl Widget<WireState> for Wire {
 
     fn update(
         &mut self,
-        _ctx: &mut druid::UpdateCtx,
-        _old_data: &WireState,
-        _data: &WireState,
+        ctx: &mut druid::UpdateCtx,
+        old_data: &WireState,
+        data: &WireState,
         _env: &druid::Env,
     ) {
+        if old_data.selected != data.selected {
+            ctx.request_paint();
+        }
     }
 
     fn layout(
@@ -92,10 +315,22 @@ impl Widget<WireState> for Wire {
         bc.constrain(data.bounding_rect().size())
     }
 
-    fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &WireState, _env: &druid::Env) {
+    fn paint(&mut self, ctx: &mut druid::PaintCtx, data: &WireState, env: &druid::Env) {
         ctx.with_save(|ctx| {
+            // segments paint themselves in canvas space
+            ctx.transform(Affine::translate(
+                Point::ORIGIN - data.bounding_rect().origin(),
+            ));
+            let style = if data.selected {
+                WireStyle {
+                    color: Some(env.get(SELECTION_COLOR)),
+                    ..data.style.clone()
+                }
+            } else {
+                data.style.clone()
+            };
             for segment in data.segments.iter() {
-                segment.paint(ctx);
+                segment.paint(ctx, &style, env);
             }
         })
     }